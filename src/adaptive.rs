@@ -0,0 +1,221 @@
+use bevy::math::DVec3;
+use bevy::prelude::Resource;
+
+use crate::constants::DEFAULT_TIMESTEP;
+use crate::physics::{GravitySettings, PhysicsBody, update_acceleration};
+use crate::watchdog::{find_non_finite, NumericalFailure};
+
+pub const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+const SAFETY: f64 = 0.9;
+const MIN_STEP_SCALE: f64 = 0.2;
+const MAX_STEP_SCALE: f64 = 5.0;
+const MAX_ATTEMPTS_PER_FRAME: i32 = 100_000; //keeps the ui responsive if the tolerance is way too strict
+
+//Dormand-Prince 5(4) coefficients
+const DP_A: [&[f64]; 6] = [
+    &[1.0 / 5.0],
+    &[3.0 / 40.0, 9.0 / 40.0],
+    &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+    &[19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0],
+    &[9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0],
+    &[35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+//difference between the 5th and the 4th order solution
+const DP_E: [f64; 7] = [71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0, -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0];
+
+#[derive(Resource, Debug)]
+pub struct AdaptiveStep {
+
+    pub tolerance: f64,
+    pub step_size: f64,
+    pub accepted_steps: i32,
+    pub rejected_steps: i32,
+
+}
+
+impl Default for AdaptiveStep {
+
+    fn default() -> Self {
+        AdaptiveStep {
+            tolerance: DEFAULT_TOLERANCE,
+            step_size: DEFAULT_TIMESTEP,
+            accepted_steps: 0,
+            rejected_steps: 0,
+        }
+    }
+
+}
+
+/// Advances all bodies from `start` by `duration` seconds with as many Dormand-Prince steps as the tolerance requires.
+/// Like [`crate::physics::integrate`], this expects the accelerations to match the current positions.
+/// After every accepted step the bodies are checked for non-finite states and handed to `after_step` with the time they reached,
+/// which may change them (e.g. merge colliding bodies) and stops the integration by returning true.
+/// Returns the simulated time that was actually covered, which is only less than `duration` if the attempt limit was hit or `after_step` stopped.
+pub fn integrate_adaptive(
+    bodies: &mut Vec<PhysicsBody>,
+    gravity: &GravitySettings,
+    start: f64,
+    duration: f64,
+    adaptive: &mut AdaptiveStep,
+    steps: &mut i32,
+    mut after_step: impl FnMut(&mut Vec<PhysicsBody>, f64) -> bool,
+) -> Result<f64, NumericalFailure> {
+    adaptive.accepted_steps = 0;
+    adaptive.rejected_steps = 0;
+    let mut stage = bodies.to_vec();
+    let mut remaining = duration;
+    while remaining > 0.0 && adaptive.accepted_steps + adaptive.rejected_steps < MAX_ATTEMPTS_PER_FRAME {
+        let step = adaptive.step_size.min(remaining);
+        let error = try_step(bodies, &mut stage, gravity, step, adaptive.tolerance, steps);
+        let scale = if error == 0.0 {
+            MAX_STEP_SCALE
+        } else if error.is_finite() {
+            (SAFETY * error.powf(-0.2)).clamp(MIN_STEP_SCALE, MAX_STEP_SCALE)
+        } else {
+            MIN_STEP_SCALE
+        };
+        if error <= 1.0 {
+            bodies.clone_from_slice(&stage);
+            remaining -= step;
+            adaptive.accepted_steps += 1;
+            let time = start + duration - remaining;
+            if let Some(failure) = find_non_finite(bodies, time) {
+                return Err(failure);
+            }
+            let stop = after_step(bodies, time);
+            stage.clone_from(bodies);
+            if stop {
+                break;
+            }
+            if step < adaptive.step_size && scale >= 1.0 {
+                continue; //the step was only cut short to hit the frame's end, so don't grow from the shortened step
+            }
+        } else {
+            adaptive.rejected_steps += 1;
+        }
+        adaptive.step_size = step * scale;
+    }
+    Ok(duration - remaining)
+}
+
+/// Takes a single Dormand-Prince step of a fixed size without any error control
+pub fn fixed_step(
+    bodies: &mut [PhysicsBody],
+    gravity: &GravitySettings,
+    step: f64,
+    steps: &mut i32,
+) {
+    let mut stage = bodies.to_vec();
+    try_step(bodies, &mut stage, gravity, step, DEFAULT_TOLERANCE, steps);
+    bodies.clone_from_slice(&stage);
+}
+
+/// Calculates one Dormand-Prince step into `stage` and returns the error relative to the tolerance (accept if <= 1)
+fn try_step(
+    bodies: &[PhysicsBody],
+    stage: &mut [PhysicsBody],
+    gravity: &GravitySettings,
+    step: f64,
+    tolerance: f64,
+    steps: &mut i32,
+) -> f64 {
+    let mut k: Vec<Vec<(DVec3, DVec3)>> = Vec::with_capacity(7);
+    k.push(bodies.iter().map(|body| (body.velocity, body.acceleration)).collect());
+    for row in DP_A.iter() {
+        for (i, body) in stage.iter_mut().enumerate() {
+            let mut dx = DVec3::ZERO;
+            let mut dv = DVec3::ZERO;
+            for (k_j, a) in k.iter().zip(row.iter()) {
+                dx += k_j[i].0 * *a;
+                dv += k_j[i].1 * *a;
+            }
+            body.position = bodies[i].position + dx * step;
+            body.velocity = bodies[i].velocity + dv * step;
+        }
+        update_acceleration(stage, gravity, steps);
+        k.push(stage.iter().map(|body| (body.velocity, body.acceleration)).collect());
+    }
+    //the last stage is the 5th order solution, so stage now holds the new state including its accelerations
+    let mut error: f64 = 0.0;
+    for (i, body) in bodies.iter().enumerate() {
+        let mut error_x = DVec3::ZERO;
+        let mut error_v = DVec3::ZERO;
+        for (k_j, e) in k.iter().zip(DP_E.iter()) {
+            error_x += k_j[i].0 * *e;
+            error_v += k_j[i].1 * *e;
+        }
+        //scale the error by the orbit around the parent, otherwise moons would be measured against their distance to the origin
+        let (relative_pos, relative_vel) = match body.parent {
+            Some(parent) => (body.position - bodies[parent].position, body.velocity - bodies[parent].velocity),
+            None => (body.position, body.velocity),
+        };
+        let error_pos = (error_x * step).length() / (tolerance * relative_pos.length().max(1.0));
+        let error_vel = (error_v * step).length() / (tolerance * relative_vel.length().max(1.0));
+        error = error.max(error_pos).max(error_vel);
+    }
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use crate::constants::G;
+
+    use super::*;
+
+    #[test]
+    fn circular_orbit_closes_after_one_period() {
+        let (mass, radius) = (2.0e30, 1.5e11);
+        let start = DVec3::new(radius, 0.0, 0.0);
+        let mut bodies = vec![
+            PhysicsBody { is_star: true, ..PhysicsBody::point(0, mass, DVec3::ZERO, DVec3::ZERO) },
+            PhysicsBody { parent: Some(0), ..PhysicsBody::point(1, 0.0, start, DVec3::new(0.0, (G * mass / radius).sqrt(), 0.0)) },
+        ];
+        let gravity = GravitySettings::default();
+        let mut adaptive = AdaptiveStep::default();
+        let mut steps = 0;
+        update_acceleration(&mut bodies, &gravity, &mut steps);
+        let period = TAU * (radius.powi(3) / (G * mass)).sqrt();
+        //in one call, so the step size is only limited by the tolerance
+        let covered = integrate_adaptive(&mut bodies, &gravity, 0.0, period, &mut adaptive, &mut steps, |_, _| false).unwrap();
+        assert_eq!(covered, period);
+        let error = (bodies[1].position - start).length() / radius;
+        assert!(error < 1e-7, "relative error {}", error);
+        //a smooth orbit needs about a hundred steps, the growth of the step size should hardly ever overshoot
+        assert!(adaptive.accepted_steps < 200, "{} steps", adaptive.accepted_steps);
+        assert!(adaptive.rejected_steps <= 5, "{} steps rejected", adaptive.rejected_steps);
+    }
+
+    #[test]
+    fn steps_shrink_towards_the_periapsis() {
+        let (mass, a, e) = (2.0e30, 1.5e11, 0.9);
+        let apoapsis = a * (1.0 + e);
+        let speed = (G * mass * (1.0 - e) / apoapsis).sqrt();
+        let mut bodies = vec![
+            PhysicsBody { is_star: true, ..PhysicsBody::point(0, mass, DVec3::ZERO, DVec3::ZERO) },
+            PhysicsBody { parent: Some(0), ..PhysicsBody::point(1, 0.0, DVec3::new(apoapsis, 0.0, 0.0), DVec3::new(0.0, speed, 0.0)) },
+        ];
+        let gravity = GravitySettings::default();
+        let mut adaptive = AdaptiveStep::default();
+        let mut steps = 0;
+        update_acceleration(&mut bodies, &gravity, &mut steps);
+        let period = TAU * (a.powi(3) / (G * mass)).sqrt();
+        let mut accepted = vec![(0.0, apoapsis)];
+        integrate_adaptive(&mut bodies, &gravity, 0.0, period, &mut adaptive, &mut steps, |bodies, time| {
+            accepted.push((time, bodies[1].position.length()));
+            false
+        }).unwrap();
+        //the last step is cut short to end at the period
+        let lengths = accepted.windows(2).map(|pair| (pair[1].0 - pair[0].0, pair[1].1)).take(accepted.len() - 2).collect::<Vec<_>>();
+        let closest = lengths.iter().min_by(|first, second| first.1.total_cmp(&second.1)).unwrap();
+        let longest = lengths.iter().max_by(|first, second| first.0.total_cmp(&second.0)).unwrap();
+        assert!(closest.1 < a * (1.0 - e) * 1.1);
+        assert!(longest.1 > a, "the longest step was at {} m", longest.1);
+        assert!(closest.0 < longest.0 / 20.0, "{} s at the periapsis, {} s at most", closest.0, longest.0);
+        let error = (bodies[1].position - DVec3::new(apoapsis, 0.0, 0.0)).length() / apoapsis;
+        assert!(error < 1e-6, "relative error {}", error);
+    }
+
+}
@@ -1,6 +1,16 @@
-use bevy::{math::DVec3, prelude::{App, Component, Entity, in_state, IntoSystemConfigs, Plugin, Query, Reflect, Update, With, Without}};
+use std::collections::VecDeque;
+use std::f64::consts::{PI, TAU};
 
-use crate::{body::{BodyChildren, Moon, Planet, SimPosition, Star}, physics::apply_physics, SimState};
+use bevy::{math::DVec3, prelude::{App, Camera, Color, Component, FixedUpdate, Gizmos, Has, in_state, IntoSystemConfigs, Plugin, Quat, Query, Reflect, Res, Transform, Update, With}};
+
+use crate::{barycenter::{Barycenter, orbit_gm, update_barycenters}, body::{BodyParent, Mass, OrbitSettings, SimPosition, Star, Velocity}, constants::M_TO_UNIT, kepler::OrbitalElements, orbit_lines::OrbitOffset, physics::{SimTime, update_translations}, SimState};
+
+const RAD_TO_ARCSEC: f64 = 206_264.806_247_096_36;
+const SECONDS_PER_CENTURY: f64 = 36525.0 * 86400.0; //julian century
+/// Number of passages kept per body
+const MAX_PASSAGES: usize = 20;
+/// Size of the passage markers relative to their distance to the camera, the Lagrange points use the same
+pub const MARKER_SIZE: f32 = 0.004;
 
 pub struct ApsisPlugin;
 
@@ -9,7 +19,10 @@ impl Plugin for ApsisPlugin {
     fn build(&self, app: &mut App) {
         app
             .register_type::<Apsis>()
-            .add_systems(Update, (update_apsis.after(apply_physics)).run_if(in_state(SimState::Simulation)));
+            .register_type::<OsculatingElements>()
+            //every physics tick is checked, at high speeds a frame can span several of them
+            .add_systems(FixedUpdate, (update_apsis.after(update_barycenters)).run_if(in_state(SimState::Simulation)))
+            .add_systems(Update, (update_elements.after(update_translations), draw_passages.after(update_translations)).run_if(in_state(SimState::Simulation)));
     }
 
 }
@@ -22,61 +35,218 @@ pub struct Apsis {
     
 }
 
-#[derive(Component, Debug, Clone, Copy, Reflect, Default)]
+#[derive(Component, Debug, Clone, Reflect, Default)]
 pub struct ApsisBody {
     
     pub aphelion: Apsis,
     pub perihelion: Apsis,
+    pub precession: Precession,
+    /// The latest passages, oldest first
+    pub passages: VecDeque<ApsisPassage>,
+    last_radial_velocity: f64,
     
 }
 
+/// A periapsis or apoapsis passage, interpolated from the orbit at the frame the radial velocity changed its sign
 #[derive(Debug, Clone, Copy, Reflect)]
+pub struct ApsisPassage {
+
+    pub apsis_type: ApsisType,
+    pub time: f64,
+    /// Relative to the parent
+    pub position: DVec3,
+    pub distance: f32,
+
+}
+
+impl ApsisPassage {
+
+    /// Finds the exact passage on the osculating orbit of the current state relative to the parent, `gm` being G * (M + m)
+    fn locate(apsis_type: ApsisType, position: DVec3, velocity: DVec3, gm: f64, time: f64) -> ApsisPassage {
+        let (elements, _) = OrbitalElements::from_state(position, velocity, gm);
+        let target = match apsis_type {
+            ApsisType::Perihelion => 0.0,
+            ApsisType::Aphelion => PI,
+        };
+        //how far the body already moved past the apsis, in mean anomaly
+        let passed = if elements.eccentricity < 1.0 {
+            (elements.mean_anomaly - target + PI).rem_euclid(TAU) - PI
+        } else {
+            elements.mean_anomaly - target
+        };
+        let mean_motion = (gm / elements.semi_major_axis.abs().powi(3)).sqrt();
+        let (apsis_position, _) = OrbitalElements { mean_anomaly: target, ..elements }.to_state(gm);
+        ApsisPassage {
+            apsis_type,
+            time: time - passed / mean_motion,
+            position: apsis_position,
+            distance: apsis_position.length() as f32,
+        }
+    }
+    
+}
+
+/// Tracks the direction of the periapsis at every periapsis passage to measure how fast the orbit precesses
+#[derive(Debug, Clone, Copy, Reflect, Default)]
+pub struct Precession {
+
+    pub passages: i32,
+    /// Unwrapped angle the periapsis moved since the first passage, in radians
+    pub angle: f64,
+    first_time: f64,
+    last_time: f64,
+    last_direction: DVec3,
+    last_radial_velocity: f64,
+
+}
+
+impl Precession {
+
+    /// Call with the position and velocity relative to the parent, `gm` being G * (M + m)
+    pub fn update(&mut self, position: DVec3, velocity: DVec3, gm: f64, time: f64) {
+        let radial_velocity = position.dot(velocity);
+        let passed_periapsis = self.last_radial_velocity < 0.0 && radial_velocity >= 0.0;
+        self.last_radial_velocity = radial_velocity;
+        if !passed_periapsis {
+            return;
+        }
+        //the eccentricity vector points at the periapsis and is far more precise than the sampled closest position
+        let angular_momentum = position.cross(velocity);
+        let direction = (velocity.cross(angular_momentum) / gm - position.normalize()).normalize();
+        if self.passages > 0 {
+            let normal = angular_momentum.normalize();
+            self.angle += self.last_direction.cross(direction).dot(normal).atan2(self.last_direction.dot(direction));
+        } else {
+            self.first_time = time;
+        }
+        self.last_direction = direction;
+        self.last_time = time;
+        self.passages += 1;
+    }
+
+    /// Measured precession rate in arcseconds per century, available after two passages
+    pub fn arcsec_per_century(&self) -> Option<f64> {
+        if self.passages < 2 || self.last_time <= self.first_time {
+            return None;
+        }
+        Some(self.angle / (self.last_time - self.first_time) * RAD_TO_ARCSEC * SECONDS_PER_CENTURY)
+    }
+    
+}
+
+/// Keplerian elements of the two-body orbit that matches the current position and velocity relative to the parent
+#[derive(Component, Debug, Clone, Copy, Reflect, Default)]
+pub struct OsculatingElements {
+
+    pub elements: OrbitalElements,
+    pub true_anomaly: f64,
+    /// Infinite when the body is on an escape trajectory
+    pub period: f64,
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
 pub enum ApsisType {
     Aphelion,
     Perihelion
 }
 
+impl ApsisType {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApsisType::Aphelion => "Apoapsis",
+            ApsisType::Perihelion => "Periapsis",
+        }
+    }
+
+}
+
 fn update_apsis(
-    stars: Query<(&SimPosition, &BodyChildren), (With<Star>, Without<Moon>, Without<Planet>)>,
-    mut planets: Query<(Entity, &SimPosition, &mut ApsisBody, &BodyChildren), (With<Planet>, Without<Star>, Without<Moon>)>,
-    mut moons: Query<(Entity, &SimPosition, &mut ApsisBody), (With<Moon>, Without<Star>, Without<Planet>)>
+    mut bodies: Query<(&SimPosition, &Velocity, &Mass, &mut ApsisBody, &BodyParent, Has<Star>)>,
+    parents: Query<(&SimPosition, &Velocity, &Mass, Has<Barycenter>)>,
+    sim_time: Res<SimTime>,
 ) {
-    for (entity, position, mut apsis, _) in &mut planets {
-        let mut parent = None;
-        for (s_pos, s_child) in &stars {
-            if s_child.0.contains(&entity) {
-                parent = Some(s_pos);
-                break;
-            }
-        }
-        if let Some(p_pos) = parent {
-            let new_distance = p_pos.0.distance(position.0) as f32;
+    for (position, velocity, mass, mut apsis, parent, star) in &mut bodies {
+        if let Ok((p_pos, p_vel, p_mass, barycenter)) = parents.get(parent.0) {
+            let relative_position = position.0 - p_pos.0;
+            let relative_velocity = velocity.0 - p_vel.0;
+            let gm = orbit_gm(p_mass.0, mass.0, barycenter && star);
+            apsis.precession.update(relative_position, relative_velocity, gm, sim_time.0);
+            let new_distance = relative_position.length() as f32;
             //perihelion
             if apsis.perihelion.distance > new_distance || apsis.perihelion.distance == 0.0 {
                 apsis.perihelion.distance = new_distance;
+                apsis.perihelion.position = relative_position;
             }
             if apsis.aphelion.distance < new_distance || apsis.perihelion.distance == 0.0 {
                 apsis.aphelion.distance = new_distance;
+                apsis.aphelion.position = relative_position;
+            } 
+            //the body moves outwards after the periapsis and inwards after the apoapsis
+            let radial_velocity = relative_position.dot(relative_velocity);
+            let passed = if apsis.last_radial_velocity < 0.0 && radial_velocity >= 0.0 {
+                Some(ApsisType::Perihelion)
+            } else if apsis.last_radial_velocity > 0.0 && radial_velocity <= 0.0 {
+                Some(ApsisType::Aphelion)
+            } else {
+                None
+            };
+            apsis.last_radial_velocity = radial_velocity;
+            if let Some(apsis_type) = passed {
+                if apsis.passages.len() >= MAX_PASSAGES {
+                    apsis.passages.pop_front();
+                }
+                apsis.passages.push_back(ApsisPassage::locate(apsis_type, relative_position, relative_velocity, gm, sim_time.0));
             } 
         }
     }
-    for (entity, position, mut apsis) in &mut moons {
-        let mut parent = None;
-        for (_, s_pos, _, s_child) in &planets {
-            if s_child.0.contains(&entity) {
-                parent = Some(s_pos);
-                break;
+}
+
+fn update_elements(
+    mut bodies: Query<(&SimPosition, &Velocity, &Mass, &mut OsculatingElements, &mut OrbitSettings, &BodyParent, Has<Star>)>,
+    parents: Query<(&SimPosition, &Velocity, &Mass, Has<Barycenter>)>,
+) {
+    for (position, velocity, mass, mut osculating, mut orbit, parent, star) in &mut bodies {
+        if let Ok((p_pos, p_vel, p_mass, barycenter)) = parents.get(parent.0) {
+            let gm = orbit_gm(p_mass.0, mass.0, barycenter && star);
+            let (elements, true_anomaly) = OrbitalElements::from_state(position.0 - p_pos.0, velocity.0 - p_vel.0, gm);
+            osculating.elements = elements;
+            osculating.true_anomaly = true_anomaly;
+            osculating.period = elements.period(gm);
+            //spaces the points of the orbit lines
+            if osculating.period.is_finite() {
+                orbit.period = osculating.period;
             }
         }
-        if let Some(p_pos) = parent {
-            let new_distance = p_pos.0.distance(position.0) as f32;
-            //perihelion
-            if apsis.perihelion.distance > new_distance || apsis.perihelion.distance == 0.0 {
-                apsis.perihelion.distance = new_distance;
-            }
-            if apsis.aphelion.distance < new_distance || apsis.perihelion.distance == 0.0 {
-                apsis.aphelion.distance = new_distance;
-            } 
+    }
+}
+
+/// Marks the recorded passages of every body whose orbit lines are drawn
+fn draw_passages(
+    bodies: Query<(&ApsisBody, &OrbitSettings, &BodyParent)>,
+    positions: Query<&SimPosition>,
+    camera: Query<&Transform, With<Camera>>,
+    offset: Res<OrbitOffset>,
+    mut gizmos: Gizmos,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    for (apsis, orbit, parent) in &bodies {
+        if !orbit.draw_lines {
+            continue;
+        }
+        let Ok(p_pos) = positions.get(parent.0) else {
+            continue;
+        };
+        for passage in &apsis.passages {
+            let position = ((p_pos.0 + passage.position) * M_TO_UNIT).as_vec3() + offset.value;
+            let color = match passage.apsis_type {
+                ApsisType::Perihelion => Color::YELLOW,
+                ApsisType::Aphelion => Color::CYAN,
+            };
+            gizmos.sphere(position, Quat::IDENTITY, camera.translation.distance(position) * MARKER_SIZE, color);
         }
     }
-}
\ No newline at end of file
+}
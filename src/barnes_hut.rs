@@ -0,0 +1,196 @@
+use bevy::math::DVec3;
+
+use crate::constants::G;
+use crate::physics::{par_map, PhysicsBody};
+
+pub const DEFAULT_THETA: f64 = 0.5;
+
+const MAX_DEPTH: usize = 32; //bodies at (almost) the same position end up in one leaf instead of splitting forever
+const NO_CHILD: u32 = u32::MAX;
+
+struct Node {
+    center: DVec3,
+    half_size: f64,
+    mass: f64,
+    center_of_mass: DVec3,
+    children: [u32; 8],
+    //range of the bodies in this node inside of Octree::order
+    start: usize,
+    len: usize,
+}
+
+impl Node {
+
+    fn is_leaf(&self) -> bool {
+        self.children.iter().all(|child| *child == NO_CHILD)
+    }
+
+    fn contains(&self, position: DVec3) -> bool {
+        let distance = (position - self.center).abs();
+        distance.x <= self.half_size && distance.y <= self.half_size && distance.z <= self.half_size
+    }
+
+}
+
+pub struct Octree {
+    nodes: Vec<Node>,
+    order: Vec<usize>,
+}
+
+impl Octree {
+
+    pub fn build(bodies: &[PhysicsBody]) -> Self {
+        let mut min = DVec3::splat(f64::MAX);
+        let mut max = DVec3::splat(f64::MIN);
+        for body in bodies {
+            min = min.min(body.position);
+            max = max.max(body.position);
+        }
+        let center = (min + max) / 2.0;
+        let half_size = ((max - min).max_element() / 2.0).max(1.0);
+        let mut tree = Octree {
+            nodes: Vec::with_capacity(bodies.len() * 2),
+            order: (0..bodies.len()).collect(),
+        };
+        if !bodies.is_empty() {
+            tree.insert(bodies, 0, bodies.len(), center, half_size, 0);
+        }
+        tree
+    }
+
+    fn insert(&mut self, bodies: &[PhysicsBody], start: usize, len: usize, center: DVec3, half_size: f64, depth: usize) -> u32 {
+        let mut mass = 0.0;
+        let mut weighted = DVec3::ZERO;
+        for index in &self.order[start..start + len] {
+            mass += bodies[*index].mass;
+            weighted += bodies[*index].position * bodies[*index].mass;
+        }
+        let center_of_mass = if mass > 0.0 { weighted / mass } else { center };
+        let node_index = self.nodes.len() as u32;
+        self.nodes.push(Node { center, half_size, mass, center_of_mass, children: [NO_CHILD; 8], start, len });
+        if len <= 1 || depth >= MAX_DEPTH {
+            return node_index;
+        }
+        let octant = |position: DVec3| -> usize {
+            (position.x > center.x) as usize | (((position.y > center.y) as usize) << 1) | (((position.z > center.z) as usize) << 2)
+        };
+        self.order[start..start + len].sort_unstable_by_key(|index| octant(bodies[*index].position));
+        let mut child_start = start;
+        for child in 0..8 {
+            let mut child_len = 0;
+            while child_start + child_len < start + len && octant(bodies[self.order[child_start + child_len]].position) == child {
+                child_len += 1;
+            }
+            if child_len > 0 {
+                let offset = DVec3::new(
+                    if child & 1 != 0 { 0.5 } else { -0.5 },
+                    if child & 2 != 0 { 0.5 } else { -0.5 },
+                    if child & 4 != 0 { 0.5 } else { -0.5 },
+                ) * half_size;
+                let child_index = self.insert(bodies, child_start, child_len, center + offset, half_size / 2.0, depth + 1);
+                self.nodes[node_index as usize].children[child] = child_index;
+            }
+            child_start += child_len;
+        }
+        node_index
+    }
+
+    /// Acceleration of the body at `index`. Nodes are approximated by their center of mass if size / distance < theta.
+    pub fn acceleration(&self, bodies: &[PhysicsBody], index: usize, theta: f64, softening_sq: f64, steps: &mut i32) -> DVec3 {
+        let mut acceleration = DVec3::ZERO;
+        if self.nodes.is_empty() {
+            return acceleration;
+        }
+        let position = bodies[index].position;
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if node.mass == 0.0 {
+                continue;
+            }
+            if node.is_leaf() {
+                for other in &self.order[node.start..node.start + node.len] {
+                    if *other == index {
+                        continue;
+                    }
+                    acceleration += pull(position, bodies[*other].position, bodies[*other].mass, softening_sq);
+                    *steps += 1;
+                }
+                continue;
+            }
+            let distance_sq = node.center_of_mass.distance_squared(position);
+            let size = node.half_size * 2.0;
+            if !node.contains(position) && size * size < theta * theta * distance_sq {
+                acceleration += pull(position, node.center_of_mass, node.mass, softening_sq);
+                *steps += 1;
+            } else {
+                stack.extend(node.children.iter().filter(|child| **child != NO_CHILD));
+            }
+        }
+        acceleration
+    }
+
+}
+
+pub fn pull(position: DVec3, other_position: DVec3, other_mass: f64, softening_sq: f64) -> DVec3 {
+    let distance = other_position - position;
+    let r_sq = distance.length_squared() + softening_sq;
+    if r_sq == 0.0 {
+        return DVec3::ZERO; //see the pair force of the direct solver
+    }
+    distance * (G * other_mass / (r_sq * r_sq.sqrt()))
+}
+
+pub fn update_acceleration(
+    bodies: &mut [PhysicsBody],
+    theta: f64,
+    softening_sq: f64,
+    parallel: bool,
+    steps: &mut i32,
+) {
+    let tree = Octree::build(bodies);
+    let accelerations: Vec<DVec3> = if parallel {
+        par_map(bodies.len(), steps, |index, steps| tree.acceleration(bodies, index, theta, softening_sq, steps))
+    } else {
+        (0..bodies.len()).map(|index| tree.acceleration(bodies, index, theta, softening_sq, steps)).collect()
+    };
+    for (body, acceleration) in bodies.iter_mut().zip(accelerations) {
+        body.acceleration = acceleration;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::physics::{GravitySettings, InteractionMode, Solver};
+
+    use super::*;
+
+    /// Accelerations and counted interactions of a flattened cloud of bodies
+    fn cloud_accelerations(solver: Solver, theta: f64) -> (Vec<DVec3>, i32) {
+        let mut bodies = (0..2000u32).map(|index| {
+            let angle = index as f64 * 2.399963;
+            let distance = 1.0e11 * (0.1 + (index as f64 * 0.618034) % 1.0);
+            let position = DVec3::new(angle.cos(), angle.sin(), (angle * 7.0).sin() * 0.1) * distance;
+            PhysicsBody::point(index, 1.0e22 * (1.0 + index as f64 % 3.0), position, DVec3::ZERO)
+        }).collect::<Vec<_>>();
+        let gravity = GravitySettings { interaction_mode: InteractionMode::Full, solver, theta, parallel: false, ..GravitySettings::default() };
+        let mut steps = 0;
+        crate::physics::update_acceleration(&mut bodies, &gravity, &mut steps);
+        (bodies.iter().map(|body| body.acceleration).collect(), steps)
+    }
+
+    #[test]
+    fn tree_approaches_the_direct_sum() {
+        let (direct, pairs) = cloud_accelerations(Solver::Direct, DEFAULT_THETA);
+        let scale = direct.iter().map(|acceleration| acceleration.length()).sum::<f64>() / direct.len() as f64;
+        let mean_error = |tree: &[DVec3]| direct.iter().zip(tree).map(|(direct, tree)| direct.distance(*tree)).sum::<f64>() / direct.len() as f64 / scale;
+        //without approximations it visits every other body, with them it gets close for a fraction of the work
+        let (exact, visits) = cloud_accelerations(Solver::BarnesHut, 0.0);
+        assert!(mean_error(&exact) < 1e-12);
+        assert_eq!(visits, pairs * 2);
+        let (approximated, interactions) = cloud_accelerations(Solver::BarnesHut, DEFAULT_THETA);
+        assert!(mean_error(&approximated) < 0.01, "{}", mean_error(&approximated));
+        assert!(interactions < pairs / 10, "{} interactions for {} pairs", interactions, pairs);
+    }
+
+}
@@ -0,0 +1,86 @@
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::DVec3;
+use bevy::prelude::{Component, Entity, in_state, IntoSystemConfigs, Query, Reflect, With, Without};
+
+use crate::body::{BodyChildren, BodyParent, Mass, SimPosition, Star, Velocity};
+use crate::constants::G;
+use crate::physics::apply_physics;
+use crate::SimState;
+
+pub struct BarycenterPlugin;
+
+impl Plugin for BarycenterPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .register_type::<Barycenter>()
+            .add_systems(FixedUpdate, (update_barycenters.after(apply_physics)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+/// Massless reference point at the center of mass of the stars and barycenters among its children.
+/// It is not simulated, but stars and circumbinary planets use it as their parent.
+/// Its `Mass` is the total mass of those members.
+#[derive(Component, Reflect, Clone, Default)]
+pub struct Barycenter;
+
+/// Mass, position and velocity of the center of mass of some bodies
+pub fn center_of_mass(bodies: impl Iterator<Item = (f64, DVec3, DVec3)>) -> (f64, DVec3, DVec3) {
+    let (mass, position, velocity) = bodies.fold((0.0, DVec3::ZERO, DVec3::ZERO), |(mass, position, velocity), (m, pos, vel)| {
+        (mass + m, position + pos * m, velocity + vel * m)
+    });
+    if mass == 0.0 {
+        return (0.0, DVec3::ZERO, DVec3::ZERO);
+    }
+    (mass, position / mass, velocity / mass)
+}
+
+/// Gravitational parameter of the orbit of a body with `mass` around a parent with `parent_mass`.
+/// A star belonging to a barycenter orbits it with the mass of the other stars, scaled by the mass ratio,
+/// which gives the same period as the mutual orbit of the stars.
+pub fn orbit_gm(parent_mass: f64, mass: f64, member_of_barycenter: bool) -> f64 {
+    if member_of_barycenter && parent_mass > mass {
+        let others = parent_mass - mass;
+        G * others.powi(3) / (parent_mass * parent_mass)
+    } else {
+        G * (parent_mass + mass)
+    }
+}
+
+pub fn update_barycenters(
+    mut barycenters: Query<(Entity, &BodyChildren, &mut Mass, &mut SimPosition, &mut Velocity), With<Barycenter>>,
+    stars: Query<(&Mass, &SimPosition, &Velocity), (With<Star>, Without<Barycenter>)>,
+    parents: Query<&BodyParent>,
+) {
+    //the innermost barycenters first, they are members of the outer ones
+    let mut order = barycenters.iter().map(|(entity, ..)| (depth(entity, &parents), entity)).collect::<Vec<_>>();
+    order.sort_by(|(first, _), (second, _)| second.cmp(first));
+    for (_, entity) in order {
+        let (_, children, ..) = barycenters.get(entity).unwrap();
+        let members = children.0.iter().filter_map(|child| {
+            match stars.get(*child) {
+                Ok((mass, position, velocity)) => Some((mass.0, position.0, velocity.0)),
+                Err(_) => barycenters.get(*child).ok().map(|(_, _, mass, position, velocity)| (mass.0, position.0, velocity.0)),
+            }
+        }).collect::<Vec<_>>();
+        let (new_mass, new_position, new_velocity) = center_of_mass(members.into_iter());
+        if new_mass == 0.0 {
+            continue;
+        }
+        let (_, _, mut mass, mut position, mut velocity) = barycenters.get_mut(entity).unwrap();
+        mass.0 = new_mass;
+        position.0 = new_position;
+        velocity.0 = new_velocity;
+    }
+}
+
+fn depth(entity: Entity, parents: &Query<&BodyParent>) -> usize {
+    let mut depth = 0;
+    let mut current = entity;
+    while let Ok(parent) = parents.get(current) {
+        current = parent.0;
+        depth += 1;
+    }
+    depth
+}
@@ -1,10 +1,10 @@
 use bevy::app::{App, Plugin};
 use bevy::math::Vec3;
-use bevy::prelude::{Children, in_state, IntoSystemConfigs, Query, Res, Resource, Transform, Update, Visibility, With, Without, Has, Name};
+use bevy::prelude::{Children, in_state, IntoSystemConfigs, Query, Res, Resource, Transform, Update, Visibility, With, Without, Name};
 use bevy::text::Text;
 use bevy_mod_billboard::text::BillboardTextBounds;
 
-use crate::body::{Diameter, Moon, Planet, Star};
+use crate::body::{BodyKind, Diameter, Mass};
 use crate::camera::{pan_orbit_camera, PanOrbitCamera};
 use crate::constants::M_TO_UNIT;
 use crate::SimState;
@@ -39,10 +39,32 @@ impl Default for BillboardSettings {
     }
 }
 
+/// Stars and planets get the biggest labels, small bodies smaller ones so they don't cover the planets
+pub fn label_font_size(kind: BodyKind) -> f32 {
+    match kind {
+        BodyKind::Star | BodyKind::Planet => 60.0,
+        BodyKind::Moon | BodyKind::Comet => 50.0,
+        BodyKind::Asteroid | BodyKind::Spacecraft | BodyKind::Barycenter => 40.0,
+    }
+}
+
+/// Shown in front of the name so the kinds can be told apart at a glance, plain ascii since the default font has no astronomical symbols
+pub fn label_marker(kind: BodyKind) -> &'static str {
+    match kind {
+        BodyKind::Star => "*",
+        BodyKind::Planet => "o",
+        BodyKind::Moon => "c",
+        BodyKind::Asteroid => ".",
+        BodyKind::Comet => "~",
+        BodyKind::Spacecraft => "^",
+        BodyKind::Barycenter => "+",
+    }
+}
+
 fn auto_scale_billboards(
-    bodies: Query<(&Children, &Transform, &Diameter, Has<Planet>, Has<Star>), Without<Text>>,
+    bodies: Query<(&Children, &Transform, &Diameter, &BodyKind), Without<Text>>,
     mut billboards: Query<(&Text, &mut Transform, &mut Visibility), With<BillboardTextBounds>>,
-    camera: Query<(&PanOrbitCamera, &Transform), (Without<BillboardTextBounds>, Without<Planet>, Without<Moon>, Without<Star>)>,
+    camera: Query<(&PanOrbitCamera, &Transform), (Without<BillboardTextBounds>, Without<Mass>)>,
     settings: Res<BillboardSettings>
 ) {
     if !settings.show {
@@ -53,16 +75,19 @@ fn auto_scale_billboards(
     }
     let (cam, c_transform) = camera.single();
     let radius = cam.radius;
-    for (children, p_transform, diameter, planet, star) in bodies.iter() {
+    for (children, p_transform, diameter, kind) in bodies.iter() {
         let distance_to_cam = c_transform.translation.distance(p_transform.translation) / STAR_IMPOSTER_DIVIDER;
-        let predicate = if planet {
-            radius > PLANET_VISIBILITY_THRESHOLD && radius < STAR_VISIBILITY_THRESHOLD
-        } else if star {
-            radius > STAR_VISIBILITY_THRESHOLD
-        } else {
-            radius < PLANET_VISIBILITY_THRESHOLD && radius > (diameter.num * 2.0)
+        let predicate = match kind {
+            BodyKind::Star => radius > STAR_VISIBILITY_THRESHOLD,
+            //comets are labeled like planets, they are usually far away from everything else
+            BodyKind::Planet | BodyKind::Comet => radius > PLANET_VISIBILITY_THRESHOLD && radius < STAR_VISIBILITY_THRESHOLD,
+            BodyKind::Moon | BodyKind::Barycenter => radius < PLANET_VISIBILITY_THRESHOLD && radius > (diameter.num * 2.0),
+            //there are often a lot of them, so they are only labeled up close
+            BodyKind::Asteroid => radius < PLANET_VISIBILITY_THRESHOLD,
+            //way too small to be seen otherwise
+            BodyKind::Spacecraft => radius < STAR_VISIBILITY_THRESHOLD,
         };
-        let offset = if star {
+        let offset = if *kind == BodyKind::Star {
             distance_to_cam
         } else {
             diameter.num / distance_to_cam * 0.01
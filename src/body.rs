@@ -3,9 +3,10 @@ use std::collections::VecDeque;
 use bevy::core::Name;
 use bevy::math::{DVec3, Vec3};
 use bevy::prelude::{Bundle, Color, Component, default, Entity, Handle, Reflect, Scene, Transform};
+use serde::Deserialize;
 
 use crate::constants::M_TO_UNIT;
-use crate::serialization::SerializedBody;
+use crate::serialization::{SerializedBody, SerializedBodyData};
 
 #[derive(Component, Clone, Default, Reflect, Copy)]
 pub struct Mass(pub f64);
@@ -83,6 +84,25 @@ pub struct Diameter {
 #[derive(Component, Reflect, Clone, Default)]
 pub struct SceneHandle(pub Handle<Scene>);
 
+/// Second zonal harmonic of a flattened body, its children feel it around the spin axis from [`AxialTilt`]
+#[derive(Component, Reflect, Clone, Copy, Default, Debug)]
+pub struct Oblateness {
+    pub j2: f64,
+    pub equatorial_radius: f64, //in meters
+}
+
+impl Oblateness {
+
+    pub fn from_data(data: &SerializedBodyData) -> Option<Self> {
+        let j2 = data.j2.filter(|j2| *j2 != 0.0)?;
+        Some(Oblateness {
+            j2,
+            equatorial_radius: data.equatorial_radius.unwrap_or(data.diameter / 2.0) * 1000.0,
+        })
+    }
+
+}
+
 //Types:
 #[derive(Component, Reflect, Clone, Default)]
 pub struct Star {
@@ -91,11 +111,56 @@ pub struct Star {
              
 }
 
-#[derive(Component, Reflect, Clone, Default)]
-pub struct Planet;
+/// What a body is, set with the `type` field of a .sim file or derived from its depth in the hierarchy
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyKind {
+    Star,
+    #[default]
+    Planet,
+    Moon,
+    Asteroid,
+    Comet,
+    Spacecraft,
+    /// Massless reference point, see [`crate::barycenter::Barycenter`]
+    Barycenter,
+}
 
-#[derive(Component, Reflect, Clone, Default)]
-pub struct Moon;
+impl BodyKind {
+
+    pub const ALL: [BodyKind; 7] = [BodyKind::Star, BodyKind::Planet, BodyKind::Moon, BodyKind::Asteroid, BodyKind::Comet, BodyKind::Spacecraft, BodyKind::Barycenter];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BodyKind::Star => "Star",
+            BodyKind::Planet => "Planet",
+            BodyKind::Moon => "Moon",
+            BodyKind::Asteroid => "Asteroid",
+            BodyKind::Comet => "Comet",
+            BodyKind::Spacecraft => "Spacecraft",
+            BodyKind::Barycenter => "Barycenter",
+        }
+    }
+
+    /// Heading of the group these bodies are collected in in the Bodies tree, stars, planets, moons and barycenters aren't grouped
+    pub fn group(&self) -> Option<&'static str> {
+        match self {
+            BodyKind::Asteroid => Some("Asteroids"),
+            BodyKind::Comet => Some("Comets"),
+            BodyKind::Spacecraft => Some("Spacecraft"),
+            _ => None,
+        }
+    }
+
+    /// The kind of a body without a `type`, orbiting a body of this kind
+    pub fn default_child(&self) -> BodyKind {
+        match self {
+            BodyKind::Star => BodyKind::Planet,
+            _ => BodyKind::Moon,
+        }
+    }
+
+}
 
 #[derive(Bundle, Clone, Default)]
 pub struct BodyBundle {
@@ -8,7 +8,7 @@ use bevy::{
     reflect::Reflect, window::Window,
 };
 
-use crate::{lock_on::LockOn, physics::apply_physics, SimState};
+use crate::{lock_on::LockOn, physics::update_translations, SimState};
 
 pub const DEFAULT_CAM_RADIUS: f32 = 150.0;
 
@@ -37,7 +37,7 @@ impl Plugin for PanOrbitCameraPlugin {
     fn build(&self, app: &mut App) {
         app
         .register_type::<PanOrbitCamera>()
-        .add_systems(Update, pan_orbit_camera.after(apply_physics).run_if(in_state(SimState::Simulation)));
+        .add_systems(Update, pan_orbit_camera.after(update_translations).run_if(in_state(SimState::Simulation)));
         //.add_system_to_stage(CoreStage::PostUpdate, pan_orbit_camera);
     }  
 } 
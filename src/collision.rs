@@ -0,0 +1,382 @@
+use bevy::app::{App, FixedUpdate, Plugin, Update};
+use bevy::core::Name;
+use bevy::log::info;
+use bevy::ecs::system::SystemParam;
+use bevy::math::DVec3;
+use bevy::prelude::{Children, Commands, DespawnRecursiveExt, Entity, Event, EventReader, EventWriter, in_state, IntoSystemConfigs, Query, Res, ResMut, Resource, Transform, With};
+use bevy::text::Text;
+use bevy_egui::{egui, EguiContexts};
+use bevy_mod_billboard::text::BillboardTextBounds;
+
+use crate::body::{BodyChildren, BodyParent, Diameter, Mass, Scale};
+use crate::constants::M_TO_UNIT;
+use crate::particles::TestParticle;
+use crate::physics::{apply_physics, GravitySettings, PhysicsBody, SimTime, update_acceleration};
+use crate::selection::SelectedEntity;
+use crate::setup::StartingTime;
+use crate::SimState;
+use crate::ui::{system_ui, UiState};
+
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<CollisionSettings>()
+            .init_resource::<CollisionLog>()
+            .add_event::<Collision>()
+            .add_systems(FixedUpdate, (apply_collisions.after(apply_physics)).run_if(in_state(SimState::Simulation)))
+            .add_systems(Update, (collision_window.after(system_ui)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+/// Settings, log and events the physics needs to resolve the collisions of a tick
+#[derive(SystemParam)]
+pub struct CollisionState<'w> {
+    pub settings: Res<'w, CollisionSettings>,
+    pub log: ResMut<'w, CollisionLog>,
+    pub events: EventWriter<'w, Collision>,
+}
+
+/// What happens when two bodies touch each other
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionMode {
+    /// Perfectly inelastic, the lighter body is absorbed by the heavier one
+    #[default]
+    Merge,
+    /// Perfectly elastic bounce along the line between both centers
+    Bounce,
+    /// Pauses the simulation and lets the bodies pass through each other when resumed
+    Pause,
+}
+
+impl CollisionMode {
+
+    pub const ALL: [CollisionMode; 3] = [CollisionMode::Merge, CollisionMode::Bounce, CollisionMode::Pause];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CollisionMode::Merge => "Merge",
+            CollisionMode::Bounce => "Bounce",
+            CollisionMode::Pause => "Pause",
+        }
+    }
+
+}
+
+#[derive(Resource, Default)]
+pub struct CollisionSettings {
+    pub mode: CollisionMode,
+}
+
+#[derive(Resource, Default)]
+pub struct CollisionLog {
+    pub entries: Vec<String>,
+    /// Pairs that already paused the simulation, they are only reported again after they separated
+    pub touching: Vec<(Entity, Entity)>,
+}
+
+/// Sent by the physics for every resolved collision, `first` is the heavier body and the one that survives a merge
+#[derive(Event, Debug, Clone)]
+pub struct Collision {
+    pub first: Entity,
+    pub second: Entity,
+    pub mode: CollisionMode,
+    /// Mass and radius of the merged body, only used for [`CollisionMode::Merge`]
+    pub mass: f64,
+    pub radius: f64,
+    pub impact_speed: f64,
+    pub time: f64,
+}
+
+/// Finds all pairs of overlapping bodies by sorting them along the x axis, so only bodies whose x extents overlap are compared.
+/// Test particles have no radius, they count as points that hit a body once they are inside of it.
+pub fn find_collisions(bodies: &[PhysicsBody]) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..bodies.len()).collect();
+    order.sort_unstable_by(|a, b| {
+        (bodies[*a].position.x - bodies[*a].radius).total_cmp(&(bodies[*b].position.x - bodies[*b].radius))
+    });
+    let mut pairs = vec![];
+    for (k, first) in order.iter().enumerate() {
+        let max_x = bodies[*first].position.x + bodies[*first].radius;
+        for second in &order[k + 1..] {
+            if bodies[*second].position.x - bodies[*second].radius > max_x {
+                break;
+            }
+            let radii = bodies[*first].radius + bodies[*second].radius;
+            if bodies[*first].position.distance_squared(bodies[*second].position) < radii * radii {
+                pairs.push((*first, *second));
+            }
+        }
+    }
+    pairs
+}
+
+/// Detects and resolves collisions after a step. Recalculates the accelerations if any body changed,
+/// so the integrators can keep relying on them. Returns true if the simulation should be paused.
+pub fn resolve_collisions(
+    bodies: &mut Vec<PhysicsBody>,
+    mode: CollisionMode,
+    log: &mut CollisionLog,
+    gravity: &GravitySettings,
+    time: f64,
+    events: &mut Vec<Collision>,
+    steps: &mut i32,
+) -> bool {
+    let pairs: Vec<(Entity, Entity)> = find_collisions(bodies).into_iter().map(|(first, second)| {
+        if bodies[first].mass >= bodies[second].mass {
+            (bodies[first].entity, bodies[second].entity)
+        } else {
+            (bodies[second].entity, bodies[first].entity)
+        }
+    }).collect();
+    log.touching.retain(|pair| pairs.contains(pair));
+    let mut changed = false;
+    let mut pause = false;
+    for (first_entity, second_entity) in pairs {
+        //a body could already be gone because of an earlier merge in this step
+        let (Some(first), Some(second)) = (index_of(bodies, first_entity), index_of(bodies, second_entity)) else {
+            continue;
+        };
+        if bodies[first].mass == 0.0 {
+            continue; //test particles pass through each other
+        }
+        let impact_speed = (bodies[first].velocity - bodies[second].velocity).length();
+        //test particles are point masses without a model, they simply disappear in whatever they hit
+        let mode = if bodies[second].mass == 0.0 && bodies[second].radius == 0.0 { CollisionMode::Merge } else { mode };
+        match mode {
+            CollisionMode::Merge => {
+                merge(bodies, first, second);
+                changed = true;
+            }
+            CollisionMode::Bounce => {
+                changed = true;
+                if !bounce(bodies, first, second) {
+                    continue; //they are already moving apart, this isn't a new collision
+                }
+            }
+            CollisionMode::Pause => {
+                if log.touching.contains(&(first_entity, second_entity)) {
+                    continue;
+                }
+                log.touching.push((first_entity, second_entity));
+                pause = true;
+            }
+        }
+        let survivor = index_of(bodies, first_entity).unwrap();
+        events.push(Collision {
+            first: first_entity,
+            second: second_entity,
+            mode,
+            mass: bodies[survivor].mass,
+            radius: bodies[survivor].radius,
+            impact_speed,
+            time,
+        });
+    }
+    if changed {
+        update_acceleration(bodies, gravity, steps);
+    }
+    pause
+}
+
+fn index_of(bodies: &[PhysicsBody], entity: Entity) -> Option<usize> {
+    bodies.iter().position(|body| body.entity == entity)
+}
+
+/// Combines `second` into `first` while conserving the mass, the momentum and the volume
+fn merge(bodies: &mut Vec<PhysicsBody>, first: usize, second: usize) {
+    let absorbed = bodies[second].clone();
+    let survivor = &mut bodies[first];
+    let mass = survivor.mass + absorbed.mass;
+    survivor.position = (survivor.position * survivor.mass + absorbed.position * absorbed.mass) / mass;
+    survivor.velocity = (survivor.velocity * survivor.mass + absorbed.velocity * absorbed.mass) / mass;
+    survivor.radius = (survivor.radius.powi(3) + absorbed.radius.powi(3)).cbrt();
+    survivor.mass = mass;
+    for (index, body) in bodies.iter_mut().enumerate() {
+        if body.parent == Some(second) {
+            //the children of the absorbed body now orbit the survivor, unless the survivor was one of them
+            body.parent = if index == first { absorbed.parent } else { Some(first) };
+        }
+    }
+    bodies.remove(second);
+    for body in bodies.iter_mut() {
+        body.parent = body.parent.map(|parent| if parent > second { parent - 1 } else { parent });
+    }
+}
+
+/// Returns false if the bodies were already separating, so only their overlap was corrected
+fn bounce(bodies: &mut [PhysicsBody], first: usize, second: usize) -> bool {
+    let distance = bodies[second].position - bodies[first].position;
+    let normal = distance.try_normalize().unwrap_or(DVec3::X);
+    let (m1, m2) = (bodies[first].mass, bodies[second].mass);
+    //push them apart until they just touch, weighted by mass so the center of mass stays in place
+    let overlap = bodies[first].radius + bodies[second].radius - distance.length();
+    bodies[first].position -= normal * overlap * m2 / (m1 + m2);
+    bodies[second].position += normal * overlap * m1 / (m1 + m2);
+    let approach = (bodies[first].velocity - bodies[second].velocity).dot(normal);
+    if approach <= 0.0 {
+        return false;
+    }
+    //written with the mass ratios, so a massless body simply reflects off a massive one
+    bodies[first].velocity -= normal * 2.0 * approach * m2 / (m1 + m2);
+    bodies[second].velocity += normal * 2.0 * approach * m1 / (m1 + m2);
+    true
+}
+
+/// Mirrors the merges of the physics in the ECS and logs every collision
+fn apply_collisions(
+    mut events: EventReader<Collision>,
+    mut commands: Commands,
+    mut bodies: Query<(&mut Name, &mut Mass, &mut Diameter, &mut Transform, &mut Scale, Option<&mut BodyChildren>, Option<&mut BodyParent>, &Children)>,
+    mut billboards: Query<&mut Text, With<BillboardTextBounds>>,
+    mut selected_entity: ResMut<SelectedEntity>,
+    mut log: ResMut<CollisionLog>,
+    mut ui_state: ResMut<UiState>,
+    starting_time: Res<StartingTime>,
+    particles: Query<(), With<TestParticle>>,
+) {
+    for event in events.read() {
+        if particles.contains(event.second) {
+            commands.entity(event.second).despawn();
+            continue;
+        }
+        let (Ok((first_name, ..)), Ok((second_name, ..))) = (bodies.get(event.first), bodies.get(event.second)) else {
+            continue;
+        };
+        let (first_name, second_name) = (first_name.to_string(), second_name.to_string());
+        let date = SimTime(event.time).date(&starting_time).format("%d.%m.%Y %H:%M");
+        let speed = event.impact_speed / 1000.0;
+        let entry = match event.mode {
+            CollisionMode::Merge => format!("{}: {} merged into {} at {:.3} km/s", date, second_name, first_name, speed),
+            CollisionMode::Bounce => format!("{}: {} bounced off {} at {:.3} km/s", date, second_name, first_name, speed),
+            CollisionMode::Pause => format!("{}: {} hit {} at {:.3} km/s", date, second_name, first_name, speed),
+        };
+        info!("{}", entry);
+        log.entries.push(entry);
+        if event.mode == CollisionMode::Pause {
+            ui_state.show_collisions = true; //notify the user why the simulation stopped
+        }
+        if event.mode != CollisionMode::Merge {
+            continue;
+        }
+
+        //take everything we need from the absorbed body before it is despawned
+        let (_, _, _, _, _, second_children, second_parent, _) = bodies.get(event.second).unwrap();
+        let second_children = second_children.map(|children| children.0.clone()).unwrap_or_default();
+        let second_parent = second_parent.map(|parent| parent.0);
+
+        let (mut name, mut mass, mut diameter, mut transform, mut scale, children, parent, b_children) = bodies.get_mut(event.first).unwrap();
+        let new_name = format!("{} + {}", first_name, second_name);
+        *name = Name::new(new_name.clone());
+        for child in b_children.iter() {
+            if let Ok(mut text) = billboards.get_mut(*child) {
+                //after the marker of the kind
+                if let Some(section) = text.sections.last_mut() {
+                    section.value = new_name.clone();
+                }
+            }
+        }
+        mass.0 = event.mass;
+        let new_diameter = (event.radius * 2.0 * M_TO_UNIT) as f32;
+        if diameter.applied && diameter.num > 0.0 {
+            let factor = new_diameter / diameter.num;
+            transform.scale *= factor;
+            scale.0 *= factor;
+        }
+        diameter.num = new_diameter;
+        let inherited: Vec<Entity> = second_children.iter().copied().filter(|child| *child != event.first).collect();
+        match children {
+            Some(mut children) => children.0.extend(inherited.iter()),
+            None => { commands.entity(event.first).insert(BodyChildren(inherited.clone())); }
+        }
+        let took_over_parent = second_children.contains(&event.first); //the survivor orbited the absorbed body, so it takes over its parent
+        if took_over_parent {
+            match (parent, second_parent) {
+                (Some(mut parent), Some(second_parent)) => parent.0 = second_parent,
+                (None, Some(second_parent)) => { commands.entity(event.first).insert(BodyParent(second_parent)); }
+                (_, None) => { commands.entity(event.first).remove::<BodyParent>(); }
+            }
+        }
+
+        for child in inherited {
+            if let Ok((.., Some(mut parent), _)) = bodies.get_mut(child) {
+                parent.0 = event.first;
+            }
+        }
+        if let Some(second_parent) = second_parent {
+            if let Ok((.., Some(mut children), _, _)) = bodies.get_mut(second_parent) {
+                children.0.retain(|child| *child != event.second);
+                if took_over_parent {
+                    children.0.push(event.first);
+                }
+            }
+        }
+        if selected_entity.entity == Some(event.second) {
+            selected_entity.change_entity(event.first);
+        }
+        commands.entity(event.second).despawn_recursive();
+    }
+}
+
+fn collision_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    log: Res<CollisionLog>,
+) {
+    if !ui_state.visible {
+        return;
+    }
+    egui::Window::new("Collisions")
+        .open(&mut ui_state.show_collisions)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            if log.entries.is_empty() {
+                ui.label("No collisions yet");
+            }
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for entry in log.entries.iter().rev() {
+                    ui.label(entry);
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn particle_falls_into_planet() {
+        let gravity = GravitySettings::default();
+        let planet = PhysicsBody { radius: 6.371e6, ..PhysicsBody::point(0, 5.97e24, DVec3::ZERO, DVec3::ZERO) };
+        let particle = PhysicsBody { parent: Some(0), ..PhysicsBody::point(1, 0.0, DVec3::new(1.0e7, 0.0, 0.0), DVec3::new(-5000.0, 0.0, 0.0)) };
+        let mut bodies = vec![planet, particle];
+        let (mut log, mut events, mut steps) = (CollisionLog::default(), vec![], 0);
+        update_acceleration(&mut bodies, &gravity, &mut steps);
+        for step in 0..10_000 {
+            crate::physics::integrate(&mut bodies, crate::physics::Integrator::Yoshida4, &gravity, 1.0, &mut steps);
+            resolve_collisions(&mut bodies, CollisionMode::Pause, &mut log, &gravity, step as f64, &mut events, &mut steps);
+            if bodies.len() == 1 {
+                break;
+            }
+        }
+        assert_eq!(bodies.len(), 1, "the particle flew through the planet");
+        assert_eq!(bodies[0].mass, 5.97e24);
+        assert_eq!(bodies[0].radius, 6.371e6);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].first, events[0].second, events[0].mode), (Entity::from_raw(0), Entity::from_raw(1), CollisionMode::Merge));
+        assert!(events[0].impact_speed > 5000.0); //it was accelerated by the planet
+    }
+
+    #[test]
+    fn particles_pass_through_each_other() {
+        let bodies = vec![
+            PhysicsBody::point(0, 0.0, DVec3::ZERO, DVec3::ZERO),
+            PhysicsBody::point(1, 0.0, DVec3::ZERO, DVec3::X),
+        ];
+        assert!(find_collisions(&bodies).is_empty());
+    }
+
+}
@@ -2,15 +2,18 @@ use std::time::Duration;
 
 use bevy::app::{App, Plugin};
 use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
-use bevy::prelude::{in_state, IntoSystemConfigs, Query, Res, ResMut, Update};
+use bevy::prelude::{in_state, IntoSystemConfigs, Query, Res, ResMut, Update, With, Without};
 use bevy_egui::{egui::{self}, EguiContexts};
 use bevy_egui::egui::RichText;
 
+use crate::adaptive::AdaptiveStep;
+use crate::barycenter::Barycenter;
 use crate::body::Mass;
 use crate::camera::PanOrbitCamera;
-use crate::physics::{NBODY_STEP_TIME, NBODY_TOTAL_TIME, NBodyStats};
+use crate::physics::{GravitySettings, Integrator, NBODY_STEP_TIME, NBODY_TOTAL_TIME, NBodyStats};
 use crate::SimState;
 use crate::ui::{system_ui, UiState};
+use crate::unit::format_seconds;
 
 pub struct DebugPlugin;
 
@@ -27,8 +30,11 @@ fn debug_window(
     mut egui_ctx: EguiContexts,
     mut ui_state: ResMut<UiState>,
     nbody_stats: Res<NBodyStats>,
+    integrator: Res<Integrator>,
+    gravity: Res<GravitySettings>,
+    adaptive: Res<AdaptiveStep>,
     diagnostics: Res<DiagnosticsStore>,
-    bodies: Query<&Mass>,
+    bodies: Query<(), (With<Mass>, Without<Barycenter>)>,
     camera: Query<&PanOrbitCamera>
 ) {
     if !ui_state.visible {
@@ -78,9 +84,23 @@ fn debug_window(
                 ui.label(format!("{:?}", body_count));
             });
             ui.horizontal(|ui| {
-                ui.label(RichText::new("N-Body steps / frame: ").strong());                            
+                ui.label(RichText::new("Gravity solver: ").strong());
+                ui.label(gravity.solver.name());
+            });
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("N-Body interactions / tick: ").strong());                            
                 ui.label(format!("{}", nbody_stats.steps));
             });
+            if integrator.is_adaptive() {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Adaptive step size: ").strong());
+                    ui.label(format_seconds(adaptive.step_size));
+                });
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Accepted / rejected steps / frame: ").strong());
+                    ui.label(format!("{} / {}", adaptive.accepted_steps, adaptive.rejected_steps));
+                });
+            }
             if let Some(frametime) = diagnostics.get(NBODY_STEP_TIME) {
                 if let Some(value) = frametime.average() {
                     // Update the value of the second section
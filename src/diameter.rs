@@ -1,5 +1,5 @@
 use bevy::{app::{App, Plugin}, math::Vec3A, prelude::{Children, GlobalTransform, Handle, in_state, IntoSystemConfigs, Mesh, Query, Res, ResMut, Transform, Update, Vec3, With}, render::primitives::{Aabb, Sphere}, scene::{SceneInstance, SceneSpawner}};
-use bevy::prelude::AssetServer;
+use bevy::prelude::{AssetServer, Condition};
 
 use crate::{body::{Diameter, Scale}, constants::M_TO_UNIT, loading::LoadingState, SimState};
 use crate::body::SceneHandle;
@@ -10,7 +10,8 @@ impl Plugin for DiameterPlugin {
     
     fn build(&self, app: &mut App) {
         app
-        .add_systems(Update, apply_real_diameter.run_if(in_state(SimState::Loading)));
+        //bodies can also be spawned while the simulation is running
+        .add_systems(Update, apply_real_diameter.run_if(in_state(SimState::Loading).or_else(in_state(SimState::Simulation))));
     }
     
 }
@@ -1,6 +1,6 @@
-use bevy::{app::{App, Plugin}, prelude::{Color, Entity, Gizmos, in_state, IntoSystemConfigs, Query, Transform, Update, With}};
+use bevy::{app::{App, Plugin}, math::DVec3, prelude::{Color, Gizmos, in_state, IntoSystemConfigs, Query, Transform, Update}};
 
-use crate::{arrows::ArrowGizmos, body::{BodyChildren, Diameter, Moon, OrbitSettings, Planet, Velocity}, camera::pan_orbit_camera, constants::M_TO_UNIT, SimState};
+use crate::{arrows::ArrowGizmos, body::{BodyParent, Diameter, OrbitSettings, Velocity}, camera::pan_orbit_camera, constants::M_TO_UNIT, SimState};
 
 pub struct DirectionPlugin;
 
@@ -14,26 +14,18 @@ impl Plugin for DirectionPlugin {
 }
 
 fn display_force_and_velocity(
-    planet_query: Query<(&Transform, &BodyChildren, &OrbitSettings, &Diameter, &Velocity), With<Planet>>,
-    moon_query: Query<(Entity, &Transform, &OrbitSettings, &Diameter, &Velocity), With<Moon>>,
+    bodies: Query<(&Transform, &OrbitSettings, &Diameter, &Velocity, Option<&BodyParent>)>,
+    velocities: Query<&Velocity>,
     mut gizmos: Gizmos
 ) {
-    for (transform, _, orbit, diameter, velocity) in &planet_query {
+    for (transform, orbit, diameter, velocity, parent) in &bodies {
         if orbit.display_force {
             gizmos.arrow(transform.translation, transform.translation + (orbit.force_direction * diameter.num as f64).as_vec3(), Color::BLUE);
         }
         if orbit.display_velocity {
-            gizmos.arrow(transform.translation, transform.translation +(velocity.0.normalize() * diameter.num as f64).as_vec3(), Color::RED);
-        }
-    }
-    for (entity, transform, orbit, diameter, velocity) in &moon_query {
-        if orbit.display_force {
-            gizmos.arrow(transform.translation, transform.translation +(orbit.force_direction * diameter.num as f64).as_vec3(), Color::BLUE);
-        }
-        if orbit.display_velocity {
-            if let Some((_, _, _, _, vel)) = planet_query.iter().find(|(_, ch, _, _, _)| ch.0.contains(&entity)) {
-                gizmos.arrow(transform.translation, transform.translation +((velocity.0 - vel.0).normalize() * diameter.num as f64).as_vec3(), Color::RED);                
-            }
+            //show the velocity relative to the parent, that's the one that matters for the orbit
+            let p_vel = parent.and_then(|parent| velocities.get(parent.0).ok()).map_or(DVec3::ZERO, |vel| vel.0);
+            gizmos.arrow(transform.translation, transform.translation + ((velocity.0 - p_vel).normalize() * diameter.num as f64).as_vec3(), Color::RED);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+
+use bevy::app::{App, FixedUpdate, Plugin, Update};
+use bevy::core::Name;
+use bevy::math::DVec3;
+use bevy::prelude::{Entity, in_state, IntoSystemConfigs, Query, Res, ResMut, Resource, With};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::body::{BodyKind, BodyParent, Diameter, Mass, SimPosition};
+use crate::constants::M_TO_UNIT;
+use crate::lock_on::LockOn;
+use crate::physics::{apply_physics, Pause, SimTime};
+use crate::selection::SelectedEntity;
+use crate::setup::StartingTime;
+use crate::SimState;
+use crate::ui::{system_ui, UiState};
+
+/// Oldest events are dropped once there are more than this
+const MAX_EVENTS: usize = 500;
+
+pub struct EclipsePlugin;
+
+impl Plugin for EclipsePlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<EclipseSettings>()
+            .init_resource::<EclipseLog>()
+            .add_systems(FixedUpdate, (detect_eclipses.after(apply_physics)).run_if(in_state(SimState::Simulation)))
+            .add_systems(Update, (eclipse_window.after(system_ui)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EclipseKind {
+    /// A moon in front of the star, seen from its planet
+    Solar,
+    /// A moon in the shadow of its planet, seen from the moon the planet covers the star
+    Lunar,
+    /// A planet in front of the star, seen from another planet of the same star
+    Transit,
+}
+
+impl EclipseKind {
+
+    pub const ALL: [EclipseKind; 3] = [EclipseKind::Solar, EclipseKind::Lunar, EclipseKind::Transit];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EclipseKind::Solar => "Solar Eclipse",
+            EclipseKind::Lunar => "Lunar Eclipse",
+            EclipseKind::Transit => "Transit",
+        }
+    }
+
+}
+
+/// What happens when an eclipse or transit begins
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EclipseReaction {
+    #[default]
+    Nothing,
+    Pause,
+    /// Selects the observer and looks at its parent from there
+    Focus,
+}
+
+impl EclipseReaction {
+
+    pub const ALL: [EclipseReaction; 3] = [EclipseReaction::Nothing, EclipseReaction::Pause, EclipseReaction::Focus];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EclipseReaction::Nothing => "Nothing",
+            EclipseReaction::Pause => "Pause",
+            EclipseReaction::Focus => "Jump Camera",
+        }
+    }
+
+}
+
+#[derive(Resource)]
+pub struct EclipseSettings {
+    pub enabled: bool,
+    pub kinds: Vec<EclipseKind>,
+    pub reaction: EclipseReaction,
+}
+
+impl Default for EclipseSettings {
+
+    fn default() -> Self {
+        EclipseSettings { enabled: true, kinds: EclipseKind::ALL.to_vec(), reaction: EclipseReaction::Nothing }
+    }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct EclipseEvent {
+    pub kind: EclipseKind,
+    pub observer: Entity,
+    /// Names of the star, occluder and observer, the bodies might be gone later
+    pub names: [String; 3],
+    pub start: f64,
+    pub peak: f64,
+    /// None while it is still going on
+    pub end: Option<f64>,
+    /// Fraction of the star's diameter that is covered at the peak, seen from the observer's center. 1 and more is total.
+    pub magnitude: f64,
+}
+
+#[derive(Resource, Default)]
+pub struct EclipseLog {
+    pub events: Vec<EclipseEvent>,
+    alignments: HashMap<(Entity, Entity, Entity), Alignment>,
+}
+
+impl EclipseLog {
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.alignments.clear();
+    }
+
+}
+
+/// The latest samples of a star, occluder and observer, to interpolate the contacts and the peak between the ticks
+#[derive(Debug, Clone, Copy, Default)]
+struct Alignment {
+    /// Time and angular separation of the last three ticks, oldest first
+    samples: [(f64, f64); 3],
+    count: usize,
+    last_margin: f64,
+    /// Index of the event while it goes on
+    event: Option<usize>,
+}
+
+impl Alignment {
+
+    fn push(&mut self, time: f64, separation: f64) {
+        self.samples.rotate_left(1);
+        self.samples[2] = (time, separation);
+        self.count += 1;
+    }
+
+    /// Time and separation of the minimum, if the middle sample is one
+    fn minimum(&self) -> Option<(f64, f64)> {
+        if self.count < 3 {
+            return None;
+        }
+        parabola_minimum(self.samples)
+    }
+
+}
+
+/// Time and value of the minimum of three samples (time, value), if the middle one is the smallest.
+/// The minimum is the vertex of the parabola through them, so it falls between the ticks.
+pub fn parabola_minimum(samples: [(f64, f64); 3]) -> Option<(f64, f64)> {
+    let [(t0, s0), (t1, s1), (t2, s2)] = samples;
+    if s1 > s0 || s1 > s2 {
+        return None;
+    }
+    let (a, b) = ((t1 - t0) * (s1 - s2), (t1 - t2) * (s1 - s0));
+    if a == b {
+        return Some((t1, s1));
+    }
+    let time = t1 - 0.5 * ((t1 - t0) * a - (t1 - t2) * b) / (a - b);
+    let value = s0 * (time - t1) * (time - t2) / ((t0 - t1) * (t0 - t2))
+        + s1 * (time - t0) * (time - t2) / ((t1 - t0) * (t1 - t2))
+        + s2 * (time - t0) * (time - t1) / ((t2 - t0) * (t2 - t1));
+    Some((time, value.min(s1)))
+}
+
+/// Finds the alignments of a star, an occluding body and an observer by their angular sizes after every physics tick
+fn detect_eclipses(
+    bodies: Query<(Entity, &Name, &SimPosition, &Diameter, Option<&BodyParent>, &BodyKind), With<Mass>>,
+    kinds: Query<&BodyKind>,
+    mut log: ResMut<EclipseLog>,
+    settings: Res<EclipseSettings>,
+    mut pause: ResMut<Pause>,
+    mut selected_entity: ResMut<SelectedEntity>,
+    mut lock_on: ResMut<LockOn>,
+    sim_time: Res<SimTime>,
+) {
+    if !settings.enabled || pause.0 {
+        return;
+    }
+    let parent_of = |entity: Entity| bodies.get(entity).ok().and_then(|(_, _, _, _, parent, _)| parent.map(|parent| parent.0));
+    let orbits_star = |entity: Entity| parent_of(entity).and_then(|parent| kinds.get(parent).ok()).is_some_and(|kind| matches!(kind, BodyKind::Star | BodyKind::Barycenter));
+    let stars = bodies.iter().filter(|(.., kind)| **kind == BodyKind::Star).collect::<Vec<_>>();
+    let others = bodies.iter().filter(|(.., kind)| **kind != BodyKind::Star).collect::<Vec<_>>();
+    let time = sim_time.0;
+    let mut started = None;
+    for (star, star_name, star_pos, star_diameter, ..) in &stars {
+        for (observer, observer_name, observer_pos, observer_diameter, ..) in &others {
+            for (occluder, occluder_name, occluder_pos, occluder_diameter, ..) in &others {
+                if observer == occluder {
+                    continue;
+                }
+                let kind = if parent_of(*occluder) == Some(*observer) {
+                    EclipseKind::Solar
+                } else if parent_of(*observer) == Some(*occluder) {
+                    EclipseKind::Lunar
+                } else if parent_of(*observer) == parent_of(*occluder) && orbits_star(*observer) {
+                    EclipseKind::Transit
+                } else {
+                    continue;
+                };
+                if !settings.kinds.contains(&kind) {
+                    continue;
+                }
+                let to_star = star_pos.0 - observer_pos.0;
+                let to_occluder = occluder_pos.0 - observer_pos.0;
+                if to_occluder.length() >= to_star.length() {
+                    //behind the star now, which also ends an eclipse that was still going on
+                    if let Some(index) = log.alignments.remove(&(*star, *occluder, *observer)).and_then(|alignment| alignment.event) {
+                        log.events[index].end = Some(time);
+                    }
+                    continue;
+                }
+                let star_radius = angular_radius(star_diameter, to_star);
+                let occluder_radius = angular_radius(occluder_diameter, to_occluder);
+                //the observer's own size lets the disks overlap from some point of its surface a bit earlier than from its center
+                let parallax = angular_radius(observer_diameter, to_occluder);
+                let separation = to_star.angle_between(to_occluder);
+                let margin = separation - (star_radius + occluder_radius + parallax);
+                let magnitude = |separation: f64| ((star_radius + occluder_radius - separation) / (2.0 * star_radius)).max(0.0);
+
+                let key = (*star, *occluder, *observer);
+                let mut alignment = log.alignments.get(&key).copied().unwrap_or_default();
+                let (last_time, _) = alignment.samples[2];
+                let contact = if alignment.count > 0 && alignment.last_margin != margin {
+                    last_time + (time - last_time) * alignment.last_margin / (alignment.last_margin - margin)
+                } else {
+                    time
+                };
+                alignment.push(time, separation);
+                match alignment.event {
+                    None if margin <= 0.0 => {
+                        log.events.push(EclipseEvent {
+                            kind,
+                            observer: *observer,
+                            names: [star_name.to_string(), occluder_name.to_string(), observer_name.to_string()],
+                            start: contact,
+                            peak: time,
+                            end: None,
+                            magnitude: magnitude(separation),
+                        });
+                        alignment.event = Some(log.events.len() - 1);
+                        started = Some(*observer);
+                    }
+                    Some(index) => {
+                        if let Some((peak, peak_separation)) = alignment.minimum() {
+                            let event = &mut log.events[index];
+                            if magnitude(peak_separation) >= event.magnitude {
+                                event.peak = peak;
+                                event.magnitude = magnitude(peak_separation);
+                            }
+                        }
+                        if margin > 0.0 {
+                            log.events[index].end = Some(contact);
+                            alignment.event = None;
+                        }
+                    }
+                    _ => {}
+                }
+                alignment.last_margin = margin;
+                log.alignments.insert(key, alignment);
+            }
+        }
+    }
+    if log.events.len() > MAX_EVENTS {
+        let removed = log.events.len() - MAX_EVENTS;
+        log.events.drain(..removed);
+        for alignment in log.alignments.values_mut() {
+            alignment.event = alignment.event.and_then(|index| index.checked_sub(removed));
+        }
+    }
+    if let Some(observer) = started {
+        match settings.reaction {
+            EclipseReaction::Nothing => {}
+            EclipseReaction::Pause => pause.0 = true,
+            EclipseReaction::Focus => {
+                selected_entity.change_entity(observer);
+                lock_on.enabled = true;
+            }
+        }
+    }
+}
+
+/// Half of the angle a body with `diameter` covers at `distance`
+fn angular_radius(diameter: &Diameter, distance: DVec3) -> f64 {
+    let radius = diameter.num as f64 / M_TO_UNIT / 2.0;
+    (radius / distance.length()).min(1.0).asin()
+}
+
+fn eclipse_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut log: ResMut<EclipseLog>,
+    mut settings: ResMut<EclipseSettings>,
+    mut selected_entity: ResMut<SelectedEntity>,
+    mut lock_on: ResMut<LockOn>,
+    starting_time: Res<StartingTime>,
+) {
+    if !ui_state.visible {
+        return;
+    }
+    let mut show = None;
+    let date = |time: f64| SimTime(time).date(&starting_time).format("%d.%m.%Y %H:%M").to_string();
+    egui::Window::new("Eclipses and Transits")
+        .open(&mut ui_state.show_eclipses)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.checkbox(&mut settings.enabled, "Detect Eclipses");
+            ui.horizontal(|ui| {
+                for kind in EclipseKind::ALL {
+                    let mut enabled = settings.kinds.contains(&kind);
+                    if ui.checkbox(&mut enabled, kind.name()).changed() {
+                        settings.kinds.retain(|other| *other != kind);
+                        if enabled {
+                            settings.kinds.push(kind);
+                        }
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("When one begins");
+                for option in EclipseReaction::ALL {
+                    ui.selectable_value(&mut settings.reaction, option, option.name());
+                }
+            });
+            if ui.button("Clear").clicked() {
+                log.clear();
+            }
+            ui.separator();
+            if log.events.is_empty() {
+                ui.label("No eclipses yet");
+            }
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                egui::Grid::new("eclipse_events").striped(true).show(ui, |ui| {
+                    for header in ["Type", "Bodies", "Start", "Peak", "End", "Magnitude", ""] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for event in log.events.iter().rev() {
+                        let [star, occluder, observer] = &event.names;
+                        ui.label(event.kind.name());
+                        ui.label(format!("{} in front of {}, seen from {}", occluder, star, observer));
+                        ui.label(date(event.start));
+                        ui.label(date(event.peak));
+                        ui.label(event.end.map(date).unwrap_or_else(|| "ongoing".to_string()));
+                        ui.label(format!("{:.3}", event.magnitude));
+                        if ui.button("Show").clicked() {
+                            show = Some(event.observer);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    if let Some(observer) = show {
+        selected_entity.change_entity(observer);
+        lock_on.enabled = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::diagnostic::DiagnosticsStore;
+    use bevy::ecs::event::Events;
+    use bevy::prelude::{Fixed, Schedule, Time, World};
+    use chrono::NaiveDateTime;
+
+    use crate::adaptive::AdaptiveStep;
+    use crate::body::{BodyBundle, Star};
+    use crate::collision::{Collision, CollisionLog, CollisionSettings};
+    use crate::constants::PHYSICS_TICK_RATE;
+    use crate::physics::{GravitySettings, Integrator, NBodyStats, StepClock, SubSteps};
+    use crate::serialization::{SerializedBody, SimulationData};
+    use crate::speed::Speed;
+    use crate::watchdog::Watchdog;
+
+    use super::*;
+
+    fn spawn(world: &mut World, entry: &SerializedBody, parent: Option<Entity>, kind: BodyKind) {
+        let kind = entry.data.kind.unwrap_or(kind);
+        let mut body = world.spawn((BodyBundle::from(entry.clone()), kind));
+        if kind == BodyKind::Star {
+            body.insert(Star::default());
+        }
+        if let Some(parent) = parent {
+            body.insert(BodyParent(parent));
+        }
+        let id = body.id();
+        for child in entry.children.iter().filter(|child| child.data.simulate) {
+            spawn(world, child, Some(id), kind.default_child());
+        }
+    }
+
+    /// The peaks the readme gives for the default scenario, with the physics and the detection running like in the app
+    #[test]
+    fn default_scenario_eclipses() {
+        let text = std::fs::read_to_string("assets/bodies.sim").unwrap();
+        let data: SimulationData = serde_json::from_str(&text).unwrap();
+        let mut world = World::new();
+        for star in data.bodies.iter().filter(|star| star.data.simulate) {
+            spawn(&mut world, star, None, BodyKind::Star);
+        }
+        //15 minute substeps and hour long ticks, the Yoshida integrator keeps the moon on time with them
+        world.insert_resource(Speed(900.0 * PHYSICS_TICK_RATE));
+        world.insert_resource(SubSteps(4));
+        world.insert_resource(Time::<Fixed>::from_hz(PHYSICS_TICK_RATE));
+        world.insert_resource(GravitySettings { interaction_mode: data.interaction_mode, ..GravitySettings::default() });
+        world.insert_resource(Integrator::Yoshida4);
+        world.insert_resource(Events::<Collision>::default());
+        world.init_resource::<Pause>();
+        world.init_resource::<SimTime>();
+        world.init_resource::<StepClock>();
+        world.init_resource::<AdaptiveStep>();
+        world.init_resource::<NBodyStats>();
+        world.init_resource::<CollisionSettings>();
+        world.init_resource::<CollisionLog>();
+        world.init_resource::<Watchdog>();
+        world.init_resource::<DiagnosticsStore>();
+        world.init_resource::<EclipseLog>();
+        world.init_resource::<EclipseSettings>();
+        world.init_resource::<SelectedEntity>();
+        world.init_resource::<LockOn>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((apply_physics, detect_eclipses.after(apply_physics)));
+        let starting_time = StartingTime(data.starting_time_millis);
+        let end = NaiveDateTime::parse_from_str("2024-04-10 00:00", "%Y-%m-%d %H:%M").unwrap();
+        //the moons of the giant planets fill the log, so the eclipses on Earth are picked up as soon as they end
+        let mut peaks = vec![];
+        while world.resource::<SimTime>().date(&starting_time) < end {
+            schedule.run(&mut world);
+            let log = world.resource::<EclipseLog>();
+            for event in &log.events {
+                if event.kind == EclipseKind::Solar && event.names == ["Sol", "Luna", "Earth"] && event.end.is_some() && !peaks.contains(&event.peak) {
+                    peaks.push(event.peak);
+                }
+            }
+        }
+        let peaks = peaks.iter().map(|peak| SimTime(*peak).date(&starting_time)).collect::<Vec<_>>();
+        for expected in ["2023-10-14 18:01", "2024-04-08 18:17"] {
+            let expected = NaiveDateTime::parse_from_str(expected, "%Y-%m-%d %H:%M").unwrap();
+            assert!(peaks.iter().any(|peak| (*peak - expected).num_minutes().abs() <= 5), "no eclipse peak near {}: {:?}", expected, peaks);
+        }
+    }
+
+}
@@ -0,0 +1,329 @@
+use std::f64::consts::TAU;
+
+use bevy::math::{DQuat, DVec3};
+use bevy::prelude::Reflect;
+
+/// Classical elements of an elliptic orbit, all angles in radians
+#[derive(Debug, Clone, Copy, Default, Reflect)]
+pub struct OrbitalElements {
+    pub semi_major_axis: f64, //in meters
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub ascending_node: f64, //longitude of the ascending node
+    pub argument_of_periapsis: f64,
+    pub mean_anomaly: f64,
+}
+
+impl OrbitalElements {
+
+    /// Position and velocity relative to the parent, `gm` being G * (M + m).
+    /// The reference plane is the x-y plane and the ascending node is measured from the x axis.
+    /// With an eccentricity above 1 the orbit is a hyperbola, the sign of the semi-major axis is ignored then.
+    pub fn to_state(&self, gm: f64) -> (DVec3, DVec3) {
+        let a = self.semi_major_axis.abs();
+        let e = self.eccentricity;
+        //in the orbital plane, with x pointing at the periapsis
+        let (position, velocity) = if e < 1.0 {
+            let anomaly = eccentric_anomaly(self.mean_anomaly, e);
+            let (sin, cos) = anomaly.sin_cos();
+            let root = (1.0 - e * e).sqrt();
+            let speed = (gm / a).sqrt() / (1.0 - e * cos);
+            (DVec3::new(a * (cos - e), a * root * sin, 0.0), DVec3::new(-sin * speed, root * cos * speed, 0.0))
+        } else {
+            let anomaly = hyperbolic_anomaly(self.mean_anomaly, e);
+            let (sinh, cosh) = (anomaly.sinh(), anomaly.cosh());
+            let root = (e * e - 1.0).sqrt();
+            let speed = (gm / a).sqrt() / (e * cosh - 1.0);
+            (DVec3::new(a * (e - cosh), a * root * sinh, 0.0), DVec3::new(-sinh * speed, root * cosh * speed, 0.0))
+        };
+        let rotation = self.orientation();
+        (rotation * position, rotation * velocity)
+    }
+
+    /// Rotates the orbital plane with x pointing at the periapsis into the reference plane
+    fn orientation(&self) -> DQuat {
+        DQuat::from_rotation_z(self.ascending_node) * DQuat::from_rotation_x(self.inclination) * DQuat::from_rotation_z(self.argument_of_periapsis)
+    }
+
+    /// The elements `time` seconds later on the same two-body orbit
+    pub fn propagate(&self, gm: f64, time: f64) -> OrbitalElements {
+        let motion = (gm / self.semi_major_axis.abs().powi(3)).sqrt();
+        OrbitalElements { mean_anomaly: self.mean_anomaly + motion * time, ..*self }
+    }
+
+    /// Distance from the parent where the orbit crosses `direction`, projected onto the orbital plane
+    pub fn radius_towards(&self, direction: DVec3) -> f64 {
+        let rotation = self.orientation();
+        let (periapsis, normal) = (rotation * DVec3::X, rotation * DVec3::Z);
+        let anomaly = periapsis.cross(direction).dot(normal).atan2(periapsis.dot(direction));
+        self.semi_major_axis * (1.0 - self.eccentricity * self.eccentricity) / (1.0 + self.eccentricity * anomaly.cos())
+    }
+
+    /// Osculating elements of the state relative to the parent, `gm` being G * (M + m).
+    /// Unbound orbits have a negative semi-major axis and their mean anomaly is the hyperbolic one.
+    /// Returns the elements and the true anomaly.
+    pub fn from_state(position: DVec3, velocity: DVec3, gm: f64) -> (OrbitalElements, f64) {
+        let distance = position.length();
+        let angular_momentum = position.cross(velocity);
+        let normal = angular_momentum.normalize_or_zero();
+        let node = DVec3::Z.cross(angular_momentum);
+        let eccentricity_vector = velocity.cross(angular_momentum) / gm - position / distance;
+        let eccentricity = eccentricity_vector.length();
+        let energy = velocity.length_squared() / 2.0 - gm / distance;
+        //equatorial orbits have no node and circular ones no periapsis, the angles are measured from the x axis or the node instead
+        let node_direction = if node.length() > 1e-12 * angular_momentum.length() { node.normalize() } else { DVec3::X };
+        let periapsis_direction = if eccentricity > 1e-10 { eccentricity_vector / eccentricity } else { node_direction };
+        let angle = |from: DVec3, to: DVec3| from.cross(to).dot(normal).atan2(from.dot(to)).rem_euclid(TAU);
+        let true_anomaly = angle(periapsis_direction, position);
+        let mean_anomaly = if eccentricity < 1.0 {
+            let anomaly = ((1.0 - eccentricity * eccentricity).sqrt() * true_anomaly.sin()).atan2(eccentricity + true_anomaly.cos());
+            (anomaly - eccentricity * anomaly.sin()).rem_euclid(TAU)
+        } else {
+            let anomaly = 2.0 * (((eccentricity - 1.0) / (eccentricity + 1.0)).sqrt() * (true_anomaly / 2.0).tan()).atanh();
+            eccentricity * anomaly.sinh() - anomaly
+        };
+        let elements = OrbitalElements {
+            semi_major_axis: -gm / (2.0 * energy),
+            eccentricity,
+            inclination: normal.z.clamp(-1.0, 1.0).acos(),
+            ascending_node: node_direction.y.atan2(node_direction.x).rem_euclid(TAU),
+            argument_of_periapsis: angle(node_direction, periapsis_direction),
+            mean_anomaly,
+        };
+        (elements, true_anomaly)
+    }
+
+    /// Time for one revolution, infinite for unbound orbits
+    pub fn period(&self, gm: f64) -> f64 {
+        if self.semi_major_axis <= 0.0 {
+            return f64::INFINITY;
+        }
+        TAU * (self.semi_major_axis.powi(3) / gm).sqrt()
+    }
+
+}
+
+/// Solves Kepler's equation M = E - e sin E for the eccentric anomaly E
+pub fn eccentric_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let mean_anomaly = mean_anomaly.rem_euclid(TAU);
+    let mut anomaly = if eccentricity > 0.8 { std::f64::consts::PI } else { mean_anomaly };
+    for _ in 0..50 {
+        let step = (anomaly - eccentricity * anomaly.sin() - mean_anomaly) / (1.0 - eccentricity * anomaly.cos());
+        anomaly -= step;
+        if step.abs() < 1e-14 {
+            break;
+        }
+    }
+    anomaly
+}
+
+/// Solves the hyperbolic Kepler equation M = e sinh H - H for the hyperbolic anomaly H
+pub fn hyperbolic_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let mut anomaly = (mean_anomaly / eccentricity).asinh();
+    for _ in 0..100 {
+        let step = (eccentricity * anomaly.sinh() - anomaly - mean_anomaly) / (eccentricity * anomaly.cosh() - 1.0);
+        anomaly -= step;
+        if step.abs() < 1e-14 {
+            break;
+        }
+    }
+    anomaly
+}
+
+/// Solves Lambert's problem: the velocities at `start` and `end` (relative to the parent) of the orbit that flies from one to the other in `time`
+/// without a full revolution, going around `normal` the same way as a prograde orbit. Uses the universal variable formulation.
+pub fn lambert(start: DVec3, end: DVec3, time: f64, gm: f64, normal: DVec3) -> Option<(DVec3, DVec3)> {
+    let (r1, r2) = (start.length(), end.length());
+    let mut angle = (start.dot(end) / (r1 * r2)).clamp(-1.0, 1.0).acos();
+    if start.cross(end).dot(normal) < 0.0 {
+        angle = TAU - angle;
+    }
+    let a = angle.sin() * (r1 * r2 / (1.0 - angle.cos())).sqrt();
+    //points on a line through the parent don't define a plane, in the same direction a is even 0 / 0
+    if a.is_nan() || a.abs() < 1e-9 * (r1 + r2) || time <= 0.0 {
+        return None;
+    }
+    let y = |z: f64| r1 + r2 + a * (z * stumpff_s(z) - 1.0) / stumpff_c(z).sqrt();
+    //the time of flight grows with z, z below 0 is a hyperbola and 4π² a full revolution
+    let flight_time = |z: f64| {
+        let y = y(z);
+        if y < 0.0 {
+            return f64::NEG_INFINITY;
+        }
+        ((y / stumpff_c(z)).powf(1.5) * stumpff_s(z) + a * y.sqrt()) / gm.sqrt()
+    };
+    let (mut low, mut high) = (-4.0 * TAU * TAU, TAU * TAU * (1.0 - 1e-6));
+    while flight_time(low) > time {
+        low *= 2.0;
+        if low < -1e8 {
+            return None;
+        }
+    }
+    if flight_time(high) < time {
+        return None;
+    }
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        if flight_time(middle) < time {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let y = y((low + high) / 2.0);
+    let f = 1.0 - y / r1;
+    let g = a * (y / gm).sqrt();
+    let g_dot = 1.0 - y / r2;
+    Some(((end - start * f) / g, (end * g_dot - start) / g))
+}
+
+/// Stumpff function S(z)
+fn stumpff_s(z: f64) -> f64 {
+    if z.abs() < 1e-3 {
+        1.0 / 6.0 - z / 120.0 + z * z / 5040.0
+    } else if z > 0.0 {
+        (z.sqrt() - z.sqrt().sin()) / z.powf(1.5)
+    } else {
+        ((-z).sqrt().sinh() - (-z).sqrt()) / (-z).powf(1.5)
+    }
+}
+
+/// Stumpff function C(z)
+fn stumpff_c(z: f64) -> f64 {
+    if z.abs() < 1e-3 {
+        0.5 - z / 24.0 + z * z / 720.0
+    } else if z > 0.0 {
+        (1.0 - z.sqrt().cos()) / z
+    } else {
+        ((-z).sqrt().cosh() - 1.0) / -z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::constants::G;
+
+    use super::*;
+
+    const SUN_GM: f64 = G * 1.9885e30;
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!((value - expected).abs() <= tolerance, "{} instead of {}", value, expected);
+    }
+
+    /// The other way round has to give the same state, whatever angles the degenerate cases picked
+    fn assert_same_state(elements: &OrbitalElements, position: DVec3, velocity: DVec3) {
+        let (back_position, back_velocity) = elements.to_state(SUN_GM);
+        assert!((back_position - position).length() < 1e-9 * position.length(), "{} instead of {}", back_position, position);
+        assert!((back_velocity - velocity).length() < 1e-9 * velocity.length(), "{} instead of {}", back_velocity, velocity);
+    }
+
+    #[test]
+    fn elliptic_round_trip() {
+        for (eccentricity, inclination) in [(0.2056, 0.122), (0.6, 2.5), (0.95, 1.0)] {
+            let elements = OrbitalElements { semi_major_axis: 5.79e10, eccentricity, inclination, ascending_node: 0.84, argument_of_periapsis: 0.51, mean_anomaly: 2.0 };
+            let (position, velocity) = elements.to_state(SUN_GM);
+            let (back, _) = OrbitalElements::from_state(position, velocity, SUN_GM);
+            assert_close(back.semi_major_axis / elements.semi_major_axis, 1.0, 1e-9);
+            assert_close(back.eccentricity, eccentricity, 1e-9);
+            assert_close(back.inclination, inclination, 1e-9);
+            assert_close(back.ascending_node, 0.84, 1e-9);
+            assert_close(back.argument_of_periapsis, 0.51, 1e-8);
+            assert_close(back.mean_anomaly, 2.0, 1e-8);
+            assert_same_state(&back, position, velocity);
+        }
+    }
+
+    #[test]
+    fn hyperbolic_round_trip() {
+        let elements = OrbitalElements { semi_major_axis: -2.0e10, eccentricity: 1.8, inclination: 0.3, ascending_node: 1.0, argument_of_periapsis: 2.0, mean_anomaly: -1.5 };
+        let (position, velocity) = elements.to_state(SUN_GM);
+        //the excess energy belongs to the semi-major axis
+        assert_close((velocity.length_squared() / 2.0 - SUN_GM / position.length()) / (SUN_GM / 4.0e10), 1.0, 1e-10);
+        let (back, _) = OrbitalElements::from_state(position, velocity, SUN_GM);
+        assert_close(back.semi_major_axis / elements.semi_major_axis, 1.0, 1e-9);
+        assert_close(back.eccentricity, 1.8, 1e-9);
+        assert_close(back.argument_of_periapsis, 2.0, 1e-8);
+        assert_close(back.mean_anomaly, -1.5, 1e-8);
+        assert!(back.period(SUN_GM).is_infinite());
+        assert_same_state(&back, position, velocity);
+    }
+
+    #[test]
+    fn hyperbolic_anomaly_solves_kepler() {
+        for (mean_anomaly, eccentricity) in [(-1.5, 1.8), (0.0, 1.1), (0.01, 1.001), (50.0, 3.0), (-1000.0, 1.2)] {
+            let anomaly = hyperbolic_anomaly(mean_anomaly, eccentricity);
+            assert_close(eccentricity * anomaly.sinh() - anomaly, mean_anomaly, 1e-9 * mean_anomaly.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn circular_orbit_measures_from_the_node() {
+        let radius = 1.496e11;
+        let elements = OrbitalElements { semi_major_axis: radius, eccentricity: 0.0, inclination: 0.4, ascending_node: 1.2, argument_of_periapsis: 0.0, mean_anomaly: 0.7 };
+        let (position, velocity) = elements.to_state(SUN_GM);
+        assert_close(velocity.length(), (SUN_GM / radius).sqrt(), 1e-6);
+        let (back, true_anomaly) = OrbitalElements::from_state(position, velocity, SUN_GM);
+        assert!(back.eccentricity < 1e-10);
+        assert_close(back.ascending_node, 1.2, 1e-9);
+        assert_close(back.argument_of_periapsis, 0.0, 1e-9);
+        assert_close(true_anomaly, 0.7, 1e-9);
+        assert_same_state(&back, position, velocity);
+    }
+
+    #[test]
+    fn equatorial_orbit_measures_from_the_x_axis() {
+        let elements = OrbitalElements { semi_major_axis: 1.0e11, eccentricity: 0.3, inclination: 0.0, ascending_node: 0.0, argument_of_periapsis: 2.2, mean_anomaly: 4.0 };
+        let (position, velocity) = elements.to_state(SUN_GM);
+        let (back, _) = OrbitalElements::from_state(position, velocity, SUN_GM);
+        assert_close(back.inclination, 0.0, 1e-9);
+        assert_close(back.ascending_node, 0.0, 1e-9);
+        assert_close(back.argument_of_periapsis, 2.2, 1e-8);
+        assert_same_state(&back, position, velocity);
+
+        //a retrograde one too, its node is still on the x axis
+        let (back, _) = OrbitalElements::from_state(position, -velocity, SUN_GM);
+        assert_close(back.inclination, PI, 1e-9);
+        assert_close(back.ascending_node, 0.0, 1e-9);
+        assert_same_state(&back, position, -velocity);
+    }
+
+    #[test]
+    fn lambert_textbook_case() {
+        //Curtis, Orbital Mechanics for Engineering Students, example 5.2: an hour around the Earth
+        let gm = 398600.0e9;
+        let (start, end) = (DVec3::new(5000.0, 10000.0, 2100.0) * 1000.0, DVec3::new(-14600.0, 2500.0, 7000.0) * 1000.0);
+        let (departure, arrival) = lambert(start, end, 3600.0, gm, DVec3::Z).unwrap();
+        assert!((departure - DVec3::new(-5.9925, 1.9254, 3.2456) * 1000.0).length() < 1.0, "{}", departure);
+        assert!((arrival - DVec3::new(-3.3125, -4.1966, -0.38529) * 1000.0).length() < 1.0, "{}", arrival);
+    }
+
+    #[test]
+    fn lambert_finds_the_orbit_between_two_states() {
+        for (eccentricity, flight) in [(0.1, 0.3), (0.5, 0.45), (0.2, 0.8)] {
+            let elements = OrbitalElements { semi_major_axis: 1.5e11, eccentricity, inclination: 0.2, ascending_node: 0.3, argument_of_periapsis: 1.0, mean_anomaly: 0.5 };
+            let time = flight * elements.period(SUN_GM);
+            let (start, start_velocity) = elements.to_state(SUN_GM);
+            let (end, end_velocity) = elements.propagate(SUN_GM, time).to_state(SUN_GM);
+            let (departure, arrival) = lambert(start, end, time, SUN_GM, start.cross(start_velocity)).unwrap();
+            assert!((departure - start_velocity).length() < 1e-6 * start_velocity.length(), "{} instead of {}", departure, start_velocity);
+            assert!((arrival - end_velocity).length() < 1e-6 * end_velocity.length(), "{} instead of {}", arrival, end_velocity);
+        }
+    }
+
+    #[test]
+    fn lambert_without_a_solution() {
+        let start = DVec3::new(1.5e11, 0.0, 0.0);
+        let year = TAU * (start.length().powi(3) / SUN_GM).sqrt();
+        //opposite and identical directions don't define a plane
+        assert!(lambert(start, -start * 1.5, year / 2.0, SUN_GM, DVec3::Z).is_none());
+        assert!(lambert(start, start * 2.0, year / 2.0, SUN_GM, DVec3::Z).is_none());
+        assert!(lambert(start, DVec3::new(0.0, 2.0e11, 0.0), 0.0, SUN_GM, DVec3::Z).is_none());
+        //without a full revolution, slower transfers go out further and further, up to the edge of the search
+        assert!(lambert(start, DVec3::new(0.0, 2.0e11, 0.0), 1.0e6 * year, SUN_GM, DVec3::Z).is_some());
+        assert!(lambert(start, DVec3::new(0.0, 2.0e11, 0.0), 1.0e30, SUN_GM, DVec3::Z).is_none());
+    }
+
+}
@@ -0,0 +1,186 @@
+use bevy::app::{App, Plugin, Update};
+use bevy::asset::AssetServer;
+use bevy::core::Name;
+use bevy::log::info;
+use bevy::math::DVec3;
+use bevy::pbr::StandardMaterial;
+use bevy::prelude::{Assets, Camera, Commands, Component, Entity, Event, EventReader, GlobalTransform, Gizmos, in_state, IntoSystemConfigs, Mesh, Quat, Query, Res, ResMut, With};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::apsis::MARKER_SIZE;
+use crate::body::{BodyKind, BodyParent, Mass, OrbitSettings, SimPosition, Velocity};
+use crate::camera::PanOrbitCamera;
+use crate::constants::M_TO_UNIT;
+use crate::orbit_lines::OrbitOffset;
+use crate::physics::{GravitySettings, InteractionMode, update_translations};
+use crate::setup::spawn_massless_body;
+use crate::SimState;
+
+pub struct LagrangePlugin;
+
+impl Plugin for LagrangePlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<SpawnAtLagrangePoint>()
+            .add_systems(Update, (draw_lagrange_points.after(update_translations), spawn_at_lagrange_points).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+/// Shows the Lagrange points of a body and its parent, added with the checkbox in the body panel
+#[derive(Component, Default)]
+pub struct LagrangePoints;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagrangePoint {
+    /// Between the two bodies
+    L1,
+    /// Behind the smaller body
+    L2,
+    /// Opposite of the smaller body
+    L3,
+    /// 60° ahead of the smaller body
+    L4,
+    /// 60° behind the smaller body
+    L5,
+}
+
+impl LagrangePoint {
+
+    pub const ALL: [LagrangePoint; 5] = [LagrangePoint::L1, LagrangePoint::L2, LagrangePoint::L3, LagrangePoint::L4, LagrangePoint::L5];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LagrangePoint::L1 => "L1",
+            LagrangePoint::L2 => "L2",
+            LagrangePoint::L3 => "L3",
+            LagrangePoint::L4 => "L4",
+            LagrangePoint::L5 => "L5",
+        }
+    }
+
+}
+
+/// Sent by the ui to spawn a massless test body at a Lagrange point of `body` and its parent
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SpawnAtLagrangePoint {
+    pub body: Entity,
+    pub point: LagrangePoint,
+}
+
+/// Positions and co-rotating velocities of L1 to L5 of a secondary body orbiting a primary, each given as (mass, position, velocity).
+/// The points keep their place in the frame that rotates and pulses with the osculating orbit of the pair, so they also work for eccentric orbits.
+pub fn lagrange_points(primary: (f64, DVec3, DVec3), secondary: (f64, DVec3, DVec3)) -> Option<[(DVec3, DVec3); 5]> {
+    let (p_mass, p_pos, p_vel) = primary;
+    let (s_mass, s_pos, s_vel) = secondary;
+    let total = p_mass + s_mass;
+    let relative_position = s_pos - p_pos;
+    let relative_velocity = s_vel - p_vel;
+    let distance = relative_position.length();
+    let angular_momentum = relative_position.cross(relative_velocity);
+    if s_mass <= 0.0 || p_mass <= 0.0 || distance == 0.0 || angular_momentum.length() == 0.0 {
+        return None;
+    }
+    let mu = s_mass / total;
+    let barycenter = (p_pos * p_mass + s_pos * s_mass) / total;
+    let barycenter_velocity = (p_vel * p_mass + s_vel * s_mass) / total;
+    //x points at the secondary, z along the orbit normal, in units of the distance with the barycenter at the origin
+    let x_axis = relative_position / distance;
+    let z_axis = angular_momentum.normalize();
+    let y_axis = z_axis.cross(x_axis);
+    let angular_velocity = angular_momentum / (distance * distance);
+    let radial_rate = relative_position.dot(relative_velocity) / distance / distance;
+
+    let hill = (mu / 3.0).cbrt();
+    let coordinates = [
+        (collinear_point(mu, 1.0 - mu - hill), 0.0),
+        (collinear_point(mu, 1.0 - mu + hill), 0.0),
+        (collinear_point(mu, -1.0 - 5.0 * mu / 12.0), 0.0),
+        (0.5 - mu, 3f64.sqrt() / 2.0),
+        (0.5 - mu, -3f64.sqrt() / 2.0),
+    ];
+    Some(coordinates.map(|(x, y)| {
+        let offset = (x_axis * x + y_axis * y) * distance;
+        (barycenter + offset, barycenter_velocity + offset * radial_rate + angular_velocity.cross(offset))
+    }))
+}
+
+/// Solves for the point on the x axis where the gravity of both bodies and the centrifugal force cancel out,
+/// starting from `guess`. The primary is at -mu and the secondary at 1 - mu.
+fn collinear_point(mu: f64, guess: f64) -> f64 {
+    let mut x = guess;
+    for _ in 0..50 {
+        let (to_primary, to_secondary) = (x + mu, x - 1.0 + mu);
+        let (cube_primary, cube_secondary) = (to_primary.abs().powi(3), to_secondary.abs().powi(3));
+        let force = x - (1.0 - mu) * to_primary / cube_primary - mu * to_secondary / cube_secondary;
+        let derivative = 1.0 + 2.0 * (1.0 - mu) / cube_primary + 2.0 * mu / cube_secondary;
+        let step = force / derivative;
+        x -= step;
+        if step.abs() < 1e-15 {
+            break;
+        }
+    }
+    x
+}
+
+fn draw_lagrange_points(
+    mut egui_ctx: EguiContexts,
+    bodies: Query<(&Mass, &SimPosition, &Velocity, &BodyParent, &OrbitSettings), With<LagrangePoints>>,
+    primaries: Query<(&Mass, &SimPosition, &Velocity)>,
+    camera: Query<(&Camera, &GlobalTransform), With<PanOrbitCamera>>,
+    offset: Res<OrbitOffset>,
+    mut gizmos: Gizmos,
+) {
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let painter = egui_ctx.ctx_mut().layer_painter(egui::LayerId::background());
+    for (mass, position, velocity, parent, orbit) in &bodies {
+        let Ok((p_mass, p_position, p_velocity)) = primaries.get(parent.0) else {
+            continue;
+        };
+        let Some(points) = lagrange_points((p_mass.0, p_position.0, p_velocity.0), (mass.0, position.0, velocity.0)) else {
+            continue;
+        };
+        let [r, g, b, _] = orbit.color.as_rgba_u8();
+        for (point, (position, _)) in LagrangePoint::ALL.iter().zip(points) {
+            let position = (position * M_TO_UNIT).as_vec3() + offset.value;
+            gizmos.sphere(position, Quat::IDENTITY, camera_transform.translation().distance(position) * MARKER_SIZE, orbit.color);
+            if let Some(screen) = camera.world_to_viewport(camera_transform, position) {
+                painter.text(egui::pos2(screen.x, screen.y - 8.0), egui::Align2::CENTER_BOTTOM, point.name(), egui::FontId::proportional(14.0), egui::Color32::from_rgb(r, g, b));
+            }
+        }
+    }
+}
+
+/// The test body orbits the primary, it only feels the secondary in the full N-body mode, so the simulation switches to it
+fn spawn_at_lagrange_points(
+    mut commands: Commands,
+    mut events: EventReader<SpawnAtLagrangePoint>,
+    mut gravity: ResMut<GravitySettings>,
+    bodies: Query<(&Name, &Mass, &SimPosition, &Velocity, &BodyParent)>,
+    primaries: Query<(&Mass, &SimPosition, &Velocity)>,
+    assets: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for event in events.read() {
+        let Ok((name, mass, position, velocity, parent)) = bodies.get(event.body) else {
+            continue;
+        };
+        let Ok((p_mass, p_position, p_velocity)) = primaries.get(parent.0) else {
+            continue;
+        };
+        let Some(points) = lagrange_points((p_mass.0, p_position.0, p_velocity.0), (mass.0, position.0, velocity.0)) else {
+            continue;
+        };
+        let index = LagrangePoint::ALL.iter().position(|point| *point == event.point).unwrap();
+        let (position, velocity) = points[index];
+        spawn_massless_body(format!("{} {}", name, event.point.name()), BodyKind::Asteroid, position, velocity, parent.0, &mut commands, &assets, &mut meshes, &mut materials);
+        if gravity.interaction_mode == InteractionMode::Hierarchical {
+            gravity.interaction_mode = InteractionMode::Full;
+            info!("Switched to the {} mode, so the test body at {} {} feels both bodies", InteractionMode::Full.name(), name, event.point.name());
+        }
+    }
+}
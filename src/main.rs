@@ -29,11 +29,22 @@ use speed::SpeedPlugin;
 use star_renderer::StarRendererPlugin;
 use ui::UIPlugin;
 
+use crate::barycenter::BarycenterPlugin;
 use crate::billboard::BodyBillboardPlugin;
+use crate::collision::CollisionPlugin;
 use crate::menu::MenuPlugin;
+use crate::particles::ParticlesPlugin;
 use crate::physics::PhysicsPlugin;
 use crate::selection::SelectionPlugin;
 use crate::setup::SetupPlugin;
+use crate::watchdog::WatchdogPlugin;
+use crate::soi::SoiPlugin;
+use crate::prediction::PredictionPlugin;
+use crate::eclipse::EclipsePlugin;
+use crate::lagrange::LagrangePlugin;
+use crate::sky_events::SkyEventsPlugin;
+use crate::transfer::TransferPlugin;
+use crate::porkchop::PorkchopPlugin;
 
 mod body;
 mod constants;
@@ -61,6 +72,21 @@ mod unit;
 mod debug;
 mod direction;
 mod arrows;
+mod adaptive;
+mod barnes_hut;
+mod collision;
+mod watchdog;
+mod barycenter;
+mod kepler;
+mod particles;
+mod soi;
+mod prediction;
+mod eclipse;
+mod lagrange;
+mod sky_events;
+mod transfer;
+mod porkchop;
+mod maneuver;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum SimState {
@@ -131,6 +157,17 @@ fn main() {
         .add_plugins(PanOrbitCameraPlugin)
         .add_plugins(SetupPlugin)
         .add_plugins(PhysicsPlugin)
+        .add_plugins(CollisionPlugin)
+        .add_plugins(WatchdogPlugin)
+        .add_plugins(BarycenterPlugin)
+        .add_plugins(ParticlesPlugin)
+        .add_plugins(SoiPlugin)
+        .add_plugins(PredictionPlugin)
+        .add_plugins(EclipsePlugin)
+        .add_plugins(LagrangePlugin)
+        .add_plugins(SkyEventsPlugin)
+        .add_plugins(TransferPlugin)
+        .add_plugins(PorkchopPlugin)
         .add_plugins(MenuPlugin)
         .add_plugins(InputPlugin)
         .add_plugins(SelectionPlugin)
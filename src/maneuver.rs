@@ -0,0 +1,307 @@
+use bevy::math::DVec3;
+use bevy::prelude::{Component, Entity};
+
+use crate::physics::PhysicsBody;
+use crate::serialization::SerializedBodyData;
+
+/// A finite burn takes at least this many steps, so its direction can follow the orbit even if the substeps are longer than the burn
+const FINITE_BURN_PIECES: f64 = 50.0;
+
+/// An impulsive or finite burn in the local frame of a spacecraft's orbit around its parent
+#[derive(Debug, Clone, Copy)]
+pub struct Burn {
+    /// Simulated time of the start
+    pub time: f64,
+    /// Prograde, normal and radial (outwards) components in m/s
+    pub delta_v: DVec3,
+    /// In seconds, 0 for an impulsive burn. A finite burn spreads its Δv evenly and follows the frame while it turns.
+    pub duration: f64,
+    /// Fraction of the Δv that was applied so far
+    pub progress: f64,
+}
+
+impl Burn {
+
+    pub fn impulsive(time: f64, delta_v: DVec3) -> Self {
+        Burn { time, delta_v, duration: 0.0, progress: 0.0 }
+    }
+
+    pub fn end(&self) -> f64 {
+        self.time + self.duration
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress >= 1.0
+    }
+
+}
+
+/// Burns a spacecraft performs at their simulated times, applied by [`crate::physics::apply_physics`]
+#[derive(Component, Debug, Clone, Default)]
+pub struct ManeuverSchedule {
+    /// Sorted by their start
+    pub burns: Vec<Burn>,
+    /// Total Δv the spacecraft can provide in m/s, burns are cut short once it is used up
+    pub budget: f64,
+    pub used: f64,
+}
+
+impl ManeuverSchedule {
+
+    /// The budget covers exactly the burns
+    pub fn new(mut burns: Vec<Burn>) -> Self {
+        burns.sort_by(|first, second| first.time.total_cmp(&second.time));
+        let budget = burns.iter().map(|burn| burn.delta_v.length()).sum();
+        ManeuverSchedule { burns, budget, used: 0.0 }
+    }
+
+    pub fn from_data(data: &SerializedBodyData) -> Option<Self> {
+        let maneuvers = data.maneuvers.as_ref().filter(|maneuvers| !maneuvers.burns.is_empty())?;
+        let mut schedule = ManeuverSchedule::new(maneuvers.burns.iter().map(|burn| Burn {
+            time: burn.time * 86400.0,
+            delta_v: DVec3::new(burn.prograde, burn.normal, burn.radial) * 1000.0,
+            duration: burn.duration.max(0.0),
+            progress: 0.0,
+        }).collect());
+        if let Some(budget) = maneuvers.delta_v_budget {
+            schedule.budget = budget * 1000.0;
+        }
+        Some(schedule)
+    }
+
+    pub fn remaining(&self) -> f64 {
+        (self.budget - self.used).max(0.0)
+    }
+
+    pub fn upcoming(&self) -> impl Iterator<Item = &Burn> {
+        self.burns.iter().filter(|burn| !burn.is_done())
+    }
+
+}
+
+/// Turns a Δv given as prograde, normal and radial components into the simulation frame, for a body moving with `velocity` at `position` relative to its parent
+pub fn local_to_global(delta_v: DVec3, position: DVec3, velocity: DVec3) -> DVec3 {
+    let prograde = velocity.normalize_or_zero();
+    let normal = position.cross(velocity).normalize_or_zero();
+    let radial = prograde.cross(normal);
+    prograde * delta_v.x + normal * delta_v.y + radial * delta_v.z
+}
+
+/// The inverse of [`local_to_global`], splits a Δv in the simulation frame into prograde, normal and radial components
+pub fn global_to_local(delta_v: DVec3, position: DVec3, velocity: DVec3) -> DVec3 {
+    let prograde = velocity.normalize_or_zero();
+    let normal = position.cross(velocity).normalize_or_zero();
+    let radial = prograde.cross(normal);
+    DVec3::new(delta_v.dot(prograde), delta_v.dot(normal), delta_v.dot(radial))
+}
+
+/// Advances the bodies from `start` by `duration` with `advance`, which gets the start and the length of each piece and returns the time it actually covered.
+/// The steps are split where burns start and end, so an impulsive burn happens at its exact time no matter how long the steps are,
+/// and every piece of a finite burn is applied half before and half after the piece.
+pub fn integrate_with_burns(
+    bodies: &mut Vec<PhysicsBody>,
+    schedules: &mut [(Entity, ManeuverSchedule)],
+    start: f64,
+    duration: f64,
+    mut advance: impl FnMut(&mut Vec<PhysicsBody>, f64, f64) -> f64,
+) -> f64 {
+    let end = start + duration;
+    let mut time = start;
+    loop {
+        fire_impulsive_burns(bodies, schedules, time);
+        if time >= end {
+            break;
+        }
+        let next = next_burn_event(schedules, time, end).unwrap_or(end);
+        let first_half = apply_finite_burns(bodies, schedules, time, next, 0.5);
+        let covered = advance(bodies, time, next - time);
+        if covered < next - time {
+            //the first half was given for the whole piece, the part the integrator didn't get to is taken back
+            take_back(bodies, schedules, &first_half, time + covered);
+        }
+        apply_finite_burns(bodies, schedules, time, time + covered, 0.5);
+        if covered < next - time {
+            time += covered;
+            break; //the adaptive integrator ran out of attempts or stopped at a collision
+        }
+        time = next;
+    }
+    //the whole duration exactly, so the step clock can count the step
+    if time >= end { duration } else { time - start }
+}
+
+/// The first start or end of a burn after `from` and before `to`, running finite burns also end the step after a piece of their duration
+fn next_burn_event(schedules: &[(Entity, ManeuverSchedule)], from: f64, to: f64) -> Option<f64> {
+    schedules.iter()
+        .flat_map(|(_, schedule)| schedule.upcoming())
+        .flat_map(|burn| {
+            let piece = (burn.duration > 0.0 && burn.time <= from).then(|| from + burn.duration / FINITE_BURN_PIECES);
+            [Some(burn.time), Some(burn.end()), piece].into_iter().flatten()
+        })
+        .filter(|time| *time > from && *time < to)
+        .min_by(|first, second| first.total_cmp(second))
+}
+
+fn fire_impulsive_burns(bodies: &mut [PhysicsBody], schedules: &mut [(Entity, ManeuverSchedule)], time: f64) {
+    for (entity, schedule) in schedules.iter_mut() {
+        for index in 0..schedule.burns.len() {
+            let burn = schedule.burns[index];
+            if burn.duration == 0.0 && !burn.is_done() && burn.time <= time {
+                apply_delta_v(bodies, *entity, schedule, index, 1.0);
+            }
+        }
+    }
+}
+
+/// What a finite burn added to a body between `start` and `end`
+struct AppliedBurn {
+    entity: Entity,
+    index: usize,
+    start: f64,
+    end: f64,
+    fraction: f64,
+    delta_v: DVec3,
+}
+
+/// Applies `share` of the part of every finite burn that falls between `from` and `to`
+fn apply_finite_burns(bodies: &mut [PhysicsBody], schedules: &mut [(Entity, ManeuverSchedule)], from: f64, to: f64, share: f64) -> Vec<AppliedBurn> {
+    let mut applied = vec![];
+    for (entity, schedule) in schedules.iter_mut() {
+        for index in 0..schedule.burns.len() {
+            let burn = schedule.burns[index];
+            if burn.duration == 0.0 || burn.is_done() {
+                continue;
+            }
+            let (start, end) = (from.max(burn.time), to.min(burn.end()));
+            if end > start {
+                let fraction = ((end - start) / burn.duration * share).min(1.0 - burn.progress);
+                let delta_v = apply_delta_v(bodies, *entity, schedule, index, fraction);
+                applied.push(AppliedBurn { entity: *entity, index, start, end, fraction, delta_v });
+            }
+        }
+    }
+    applied
+}
+
+/// Undoes the part of the applied burns that falls after `from`, budget included
+fn take_back(bodies: &mut [PhysicsBody], schedules: &mut [(Entity, ManeuverSchedule)], applied: &[AppliedBurn], from: f64) {
+    for burn in applied {
+        let Some((_, schedule)) = schedules.iter_mut().find(|(entity, _)| *entity == burn.entity) else {
+            continue;
+        };
+        let share = ((burn.end - from.max(burn.start)) / (burn.end - burn.start)).max(0.0);
+        schedule.burns[burn.index].progress -= burn.fraction * share;
+        schedule.used -= burn.delta_v.length() * share;
+        if let Some(body) = bodies.iter_mut().find(|body| body.entity == burn.entity) {
+            body.velocity -= burn.delta_v * share;
+        }
+    }
+}
+
+/// Adds `fraction` of a burn to the velocity of the body, as far as the budget allows, and returns the change of the velocity
+fn apply_delta_v(bodies: &mut [PhysicsBody], entity: Entity, schedule: &mut ManeuverSchedule, index: usize, fraction: f64) -> DVec3 {
+    //collisions can remove bodies, so the index is looked up every time
+    let Some(body) = bodies.iter().position(|body| body.entity == entity) else {
+        return DVec3::ZERO;
+    };
+    let burn = &mut schedule.burns[index];
+    //the pieces of a finite burn don't add up exactly
+    burn.progress = if burn.progress + fraction > 1.0 - 1e-9 { 1.0 } else { burn.progress + fraction };
+    let (position, velocity) = match bodies[body].parent {
+        Some(parent) => (bodies[body].position - bodies[parent].position, bodies[body].velocity - bodies[parent].velocity),
+        None => (bodies[body].position, bodies[body].velocity),
+    };
+    let delta_v = local_to_global(burn.delta_v * fraction, position, velocity);
+    let available = (schedule.budget - schedule.used).max(0.0);
+    let magnitude = delta_v.length().min(available);
+    schedule.used += magnitude;
+    let change = delta_v.normalize_or_zero() * magnitude;
+    bodies[body].velocity += change;
+    change
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A spacecraft without a parent moving along x, so prograde is x as well
+    fn spacecraft() -> Vec<PhysicsBody> {
+        vec![PhysicsBody::point(0, 0.0, DVec3::ZERO, DVec3::new(1000.0, 0.0, 0.0))]
+    }
+
+    /// Moves the bodies without any gravity and records the steps
+    fn drift(steps: &mut Vec<f64>) -> impl FnMut(&mut Vec<PhysicsBody>, f64, f64) -> f64 + '_ {
+        |bodies, _, duration| {
+            for body in bodies.iter_mut() {
+                body.position += body.velocity * duration;
+            }
+            steps.push(duration);
+            duration
+        }
+    }
+
+    #[test]
+    fn impulsive_burn_splits_the_step() {
+        let mut bodies = spacecraft();
+        let mut schedules = [(Entity::from_raw(0), ManeuverSchedule::new(vec![Burn::impulsive(1300.0, DVec3::new(10.0, 0.0, 0.0))]))];
+        let mut steps = vec![];
+        let covered = integrate_with_burns(&mut bodies, &mut schedules, 1000.0, 1000.0, drift(&mut steps));
+        assert_eq!(covered, 1000.0);
+        assert_eq!(steps, [300.0, 700.0]);
+        assert_eq!(bodies[0].velocity.x, 1010.0);
+        assert_eq!(bodies[0].position.x, 1000.0 * 1000.0 + 10.0 * 700.0);
+        assert!(schedules[0].1.burns[0].is_done());
+    }
+
+    #[test]
+    fn finite_burn_pieces_add_up() {
+        for step in [1000.0, 100.0, 7.0] {
+            let burn = Burn { time: 100.0, delta_v: DVec3::new(20.0, 0.0, 0.0), duration: 500.0, progress: 0.0 };
+            let mut bodies = spacecraft();
+            let mut schedules = [(Entity::from_raw(0), ManeuverSchedule::new(vec![burn]))];
+            let mut steps = vec![];
+            let mut time = 0.0;
+            while time < 1000.0 {
+                time += integrate_with_burns(&mut bodies, &mut schedules, time, step, drift(&mut steps));
+            }
+            let schedule = &schedules[0].1;
+            assert!(schedule.burns[0].is_done());
+            assert!((bodies[0].velocity.x - 1020.0).abs() < 1e-9, "{} with steps of {} s", bodies[0].velocity.x, step);
+            assert!((schedule.used - 20.0).abs() < 1e-9);
+            //a long step is cut into pieces, so the direction could follow a turning orbit
+            assert!(steps.iter().filter(|piece| **piece <= 500.0 / FINITE_BURN_PIECES + 1e-9).count() >= FINITE_BURN_PIECES as usize - 1);
+        }
+    }
+
+    #[test]
+    fn empty_budget_cuts_the_burn_short() {
+        let burns = vec![
+            Burn { time: 0.0, delta_v: DVec3::new(20.0, 0.0, 0.0), duration: 100.0, progress: 0.0 },
+            Burn::impulsive(500.0, DVec3::new(5.0, 0.0, 0.0)),
+        ];
+        let mut bodies = spacecraft();
+        let mut schedules = [(Entity::from_raw(0), ManeuverSchedule { budget: 12.0, ..ManeuverSchedule::new(burns) })];
+        integrate_with_burns(&mut bodies, &mut schedules, 0.0, 1000.0, drift(&mut vec![]));
+        let schedule = &schedules[0].1;
+        assert!((bodies[0].velocity.x - 1012.0).abs() < 1e-9);
+        assert_eq!(schedule.remaining(), 0.0);
+        //the burns still count as done, there is nothing left to fire them with
+        assert!(schedule.burns.iter().all(Burn::is_done));
+        assert_eq!(schedule.upcoming().count(), 0);
+    }
+
+    #[test]
+    fn stopped_step_keeps_only_the_covered_burn() {
+        let burn = Burn { time: 0.0, delta_v: DVec3::new(20.0, 0.0, 0.0), duration: 1000.0, progress: 0.0 };
+        let mut bodies = spacecraft();
+        let mut schedules = [(Entity::from_raw(0), ManeuverSchedule::new(vec![burn]))];
+        //the integrator gives up after a quarter of the piece
+        let covered = integrate_with_burns(&mut bodies, &mut schedules, 0.0, 10.0, |_, _, duration| duration / 4.0);
+        assert_eq!(covered, 2.5);
+        let schedule = &schedules[0].1;
+        assert!((schedule.burns[0].progress - 0.0025).abs() < 1e-12);
+        assert!((schedule.used - 0.05).abs() < 1e-12);
+        assert!((bodies[0].velocity.x - 1000.05).abs() < 1e-9);
+    }
+
+}
@@ -1,6 +1,6 @@
-use bevy::{prelude::{App, Entity, Gizmos, in_state, IntoSystemConfigs, Plugin, PreUpdate, Query, Res, Resource, Transform, Vec3, With, Without}, time::Time};
+use bevy::{math::DVec3, prelude::{App, Gizmos, in_state, IntoSystemConfigs, Plugin, Query, Res, Resource, Transform, Update, Vec3}, time::Time};
 
-use crate::{body::{BodyChildren, Moon, OrbitSettings, Planet, SimPosition, Star}, constants::M_TO_UNIT, physics::{apply_physics, Pause, SubSteps}, SimState, speed::Speed};
+use crate::{body::{BodyParent, OrbitSettings, SimPosition}, constants::M_TO_UNIT, physics::{Pause, SubSteps, update_translations}, SimState, speed::Speed};
 
 pub struct OrbitLinePlugin;
 
@@ -8,7 +8,8 @@ impl Plugin for OrbitLinePlugin {
     fn build(&self, app: &mut App) {
         app
             .init_resource::<OrbitOffset>()
-            .add_systems(PreUpdate, (update_lines.after(apply_physics), draw_orbit_line.after(update_lines)).run_if(in_state(SimState::Simulation)));
+            //Update runs after the physics ticks of the frame, so the lines end at the rendered positions
+            .add_systems(Update, (update_lines.after(update_translations), draw_orbit_line.after(update_lines)).run_if(in_state(SimState::Simulation)));
     }
 }
 
@@ -34,8 +35,8 @@ impl Default for OrbitOffset {
 const MULTIPLIER: f32 = 0.0001;
 
 fn update_lines(
-    mut planet_query: Query<(&mut OrbitSettings, &SimPosition, &BodyChildren), (With<Planet>, Without<Moon>, Without<Star>)>,
-    mut moon_query: Query<(Entity, &SimPosition, &mut OrbitSettings), (With<Moon>, Without<Planet>, Without<Star>)>,
+    mut bodies: Query<(&SimPosition, &mut OrbitSettings, Option<&BodyParent>)>,
+    positions: Query<&SimPosition>,
     time: Res<Time>,
     speed: Res<Speed>,
     substeps: Res<SubSteps>,
@@ -44,59 +45,48 @@ fn update_lines(
     if pause.0 {
         return;
     }
-    for (mut orbit, pos, _) in &mut planet_query {
+    for (pos, mut orbit, parent) in &mut bodies {
         if orbit.draw_lines {
+            //the points are stored relative to the parent, so the orbit moves along with it
+            let p_pos = match parent {
+                Some(parent) => match positions.get(parent.0) {
+                    Ok(p_pos) => p_pos.0,
+                    Err(_) => continue,
+                },
+                None => DVec3::ZERO,
+            };
             let speed = speed.0 as f32 * (substeps.0 as f32);
             let max_step = (orbit.period as f32 / speed) * MULTIPLIER;
             if orbit.step >= max_step {
-                orbit.lines.push_back((pos.0 * M_TO_UNIT).as_vec3());
-              //  insert_at_nearest_distance(&mut orbit.lines, (pos.0 * M_TO_UNIT).as_vec3());
+                let raw_p_pos = (p_pos * M_TO_UNIT).as_vec3();
+                let raw_pos = (pos.0 * M_TO_UNIT).as_vec3();
+                orbit.lines.push_back(raw_pos - raw_p_pos);
+                //insert_at_nearest_distance(&mut orbit.lines, raw_pos - raw_p_pos);
                 orbit.step = 0.0;
             } else {
                 orbit.step += time.delta_seconds();
             }
         }
     }
-    for (entity, pos, mut orbit) in &mut moon_query {
-        if orbit.draw_lines {
-            if let Some((_, p_pos, _)) = planet_query.iter().find(|(_, _, children)| {
-                children.0.contains(&entity)
-            }) {
-                let speed = speed.0 as f32 * (substeps.0 as f32);
-                let max_step = (orbit.period as f32 / speed) * MULTIPLIER;
-                if orbit.step >= max_step {
-                    let raw_p_pos = (p_pos.0 * M_TO_UNIT).as_vec3();
-                    let raw_pos = (pos.0 * M_TO_UNIT).as_vec3();
-                    orbit.lines.push_back(raw_pos - raw_p_pos);   
-                    //insert_at_nearest_distance(&mut orbit.lines, raw_pos - raw_p_pos);
-                    orbit.step = 0.0;
-                } else {
-                    orbit.step += time.delta_seconds();
-                }
-            }
-        }
-    }
 }
 
 fn draw_orbit_line(
     offset: Res<OrbitOffset>,
-    planet_query: Query<(&OrbitSettings, &SimPosition, &BodyChildren, &Transform), (With<Planet>, Without<Moon>, Without<Star>)>,
-    moon_query: Query<(Entity, &OrbitSettings, &Transform), (With<Moon>, Without<Planet>, Without<Star>)>,
+    bodies: Query<(&OrbitSettings, &Transform, Option<&BodyParent>)>,
+    positions: Query<&SimPosition>,
     mut gizmos: Gizmos
 ) {
-    for (orbit, _, _, transform) in &planet_query {
+    for (orbit, transform, parent) in &bodies {
         if orbit.draw_lines {
-            draw_lines(orbit, offset.value, &mut gizmos, transform.translation)
-        }
-    }
-    for (entity, orbit, transform) in &moon_query {
-        if orbit.draw_lines {
-            if let Some((_, p_pos, _, _)) = planet_query.iter().find(|(_, _, children, _)| {
-                children.0.contains(&entity)
-            }) {
-                let raw_p_pos = (p_pos.0 * M_TO_UNIT).as_vec3();
-                draw_lines(orbit, offset.value + raw_p_pos, &mut gizmos, transform.translation)
-            }
+            let p_pos = match parent {
+                Some(parent) => match positions.get(parent.0) {
+                    Ok(p_pos) => p_pos.0,
+                    Err(_) => continue,
+                },
+                None => DVec3::ZERO,
+            };
+            let raw_p_pos = (p_pos * M_TO_UNIT).as_vec3();
+            draw_lines(orbit, offset.value + raw_p_pos, &mut gizmos, transform.translation)
         }
     }
 }
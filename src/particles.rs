@@ -0,0 +1,136 @@
+use bevy::app::{App, Plugin, Update};
+use bevy::core::Name;
+use bevy::math::{DQuat, DVec3};
+use bevy::pbr::{PbrBundle, StandardMaterial};
+use bevy::prelude::{Assets, Color, Commands, Component, default, Entity, Handle, in_state, IntoSystemConfigs, Mesh, OnEnter, Query, Res, ResMut, With};
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::view::NoFrustumCulling;
+
+use crate::body::{Acceleration, BodyParent, SimPosition, Velocity};
+use crate::constants::{G, M_TO_UNIT};
+use crate::kepler::OrbitalElements;
+use crate::orbit_lines::OrbitOffset;
+use crate::physics::update_translations;
+use crate::serialization::{SerializedBodyData, SerializedParticles};
+use crate::SimState;
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(OnEnter(SimState::Simulation), spawn_particle_cloud)
+            .add_systems(Update, (update_particle_cloud.after(update_translations)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+/// Massless body without a model, it only feels the gravity of the massive bodies.
+/// They are not part of the Bodies tree and are all drawn together as the points of a single mesh.
+#[derive(Component, Default)]
+pub struct TestParticle;
+
+/// The mesh all test particles are drawn with
+#[derive(Component, Default)]
+pub struct ParticleCloud;
+
+/// Small xorshift generator, so the same .sim file always produces the same particles
+struct Random(u64);
+
+impl Random {
+
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next()
+    }
+
+}
+
+/// Spawns a ring or belt of test particles on random orbits around a body
+pub fn spawn_particles(
+    particles: &SerializedParticles,
+    parent: Entity,
+    parent_data: &SerializedBodyData,
+    seed: u64,
+    commands: &mut Commands,
+) {
+    let gm = G * parent_data.mass;
+    let parent_position = DVec3::from(parent_data.starting_position) * 1000.0;
+    let parent_velocity = DVec3::from(parent_data.starting_velocity) * 1000.0;
+    //rings lie in the equatorial plane, which is tilted around the x axis like the body's model
+    let plane = if particles.equatorial {
+        DQuat::from_rotation_x((parent_data.axial_tilt as f64).to_radians())
+    } else {
+        DQuat::IDENTITY
+    };
+    let (inner, outer) = (particles.inner_radius * 1000.0, particles.outer_radius * 1000.0);
+    let mut random = Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1);
+    let batch = (0..particles.count).map(|_| {
+        let elements = OrbitalElements {
+            semi_major_axis: random.range(inner * inner, outer * outer).sqrt(), //evenly spread over the area
+            eccentricity: random.range(0.0, particles.max_eccentricity),
+            inclination: random.range(0.0, particles.max_inclination.to_radians()),
+            ascending_node: random.range(0.0, std::f64::consts::TAU),
+            argument_of_periapsis: random.range(0.0, std::f64::consts::TAU),
+            mean_anomaly: random.range(0.0, std::f64::consts::TAU),
+        };
+        let (position, velocity) = elements.to_state(gm);
+        (
+            TestParticle,
+            SimPosition(parent_position + plane * position),
+            Velocity(parent_velocity + plane * velocity),
+            Acceleration::default(),
+            BodyParent(parent),
+        )
+    }).collect::<Vec<_>>();
+    commands.spawn_batch(batch);
+}
+
+fn spawn_particle_cloud(
+    mut commands: Commands,
+    particles: Query<(), With<TestParticle>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if particles.is_empty() {
+        return;
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::PointList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32; 3]; particles.iter().count()]);
+    commands.spawn(PbrBundle {
+        mesh: meshes.add(mesh),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgb(0.8, 0.8, 0.8),
+            unlit: true,
+            ..default()
+        }),
+        ..default()
+    })
+        .insert(ParticleCloud)
+        .insert(NoFrustumCulling) //the bounding box would be outdated as soon as the particles move
+        .insert(Name::new("Test Particles"));
+}
+
+fn update_particle_cloud(
+    particles: Query<&SimPosition, With<TestParticle>>,
+    cloud: Query<&Handle<Mesh>, With<ParticleCloud>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    offset: Res<OrbitOffset>,
+) {
+    let Ok(handle) = cloud.get_single() else {
+        return;
+    };
+    let Some(mesh) = meshes.get_mut(handle) else {
+        return;
+    };
+    //the same offset update_translations applies to the bodies
+    let positions: Vec<[f32; 3]> = particles.iter().map(|position| (position.0 * M_TO_UNIT + offset.value.as_dvec3()).as_vec3().to_array()).collect();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+}
@@ -1,40 +1,104 @@
+use std::collections::HashMap;
 use std::time::Instant;
 
-use bevy::app::{App, Plugin, Update};
+use bevy::app::{App, FixedUpdate, Plugin, Update};
 use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
+use bevy::log::error;
 use bevy::math::{DVec3, Vec3};
-use bevy::prelude::{Entity, in_state, IntoSystemConfigs, Mut, Query, Res, ResMut, Resource, Time, Transform, Has, Children};
-use bevy::reflect::List;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use bevy::prelude::{Entity, Fixed, in_state, IntoSystemConfigs, Query, Reflect, Res, ResMut, Resource, Time, Transform, Has, With, Without};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
 
-use crate::body::{Acceleration, Mass, OrbitSettings, SimPosition, Velocity, Star, Planet, BodyChildren};
-use crate::constants::{DEFAULT_SUB_STEPS, G, M_TO_UNIT};
+use crate::adaptive::{AdaptiveStep, fixed_step, integrate_adaptive};
+use crate::barnes_hut::{self, DEFAULT_THETA};
+use crate::collision::{CollisionState, resolve_collisions};
+use crate::body::{Acceleration, AxialTilt, Diameter, Mass, Oblateness, OrbitSettings, SimPosition, Velocity, Star, BodyParent};
+use crate::constants::{C, DEFAULT_SUB_STEPS, G, M_TO_UNIT, PHYSICS_TICK_RATE};
+use crate::maneuver::{integrate_with_burns, ManeuverSchedule};
 use crate::orbit_lines::OrbitOffset;
+use crate::particles::TestParticle;
 use crate::selection::SelectedEntity;
+use crate::setup::StartingTime;
 use crate::SimState;
 use crate::speed::Speed;
+use crate::watchdog::{find_non_finite, Watchdog};
 
 pub struct PhysicsPlugin;
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
         app
+            .insert_resource(Time::<Fixed>::from_hz(PHYSICS_TICK_RATE))
             .init_resource::<Pause>()
+            .init_resource::<SimTime>()
+            .init_resource::<StepClock>()
             .init_resource::<SubSteps>()
+            .init_resource::<Integrator>()
+            .init_resource::<GravitySettings>()
+            .init_resource::<AdaptiveStep>()
             .init_resource::<NBodyStats>()
+            .register_type::<SimTime>()
             .register_type::<Velocity>()
             .register_type::<Acceleration>()
             .register_type::<Mass>()
             .register_type::<SimPosition>()
             .register_type::<OrbitSettings>()
+            .register_type::<Oblateness>()
             .register_diagnostic(Diagnostic::new(NBODY_STEP_TIME, "nbody_step_time", 10))
             .register_diagnostic(Diagnostic::new(NBODY_TOTAL_TIME, "nbody_total_time", 10))
-            .add_systems(Update, (apply_physics).run_if(in_state(SimState::Simulation)));
+            .add_systems(FixedUpdate, (apply_physics).run_if(in_state(SimState::Simulation)))
+            .add_systems(Update, (update_translations).run_if(in_state(SimState::Simulation)));
     }
 }
 
 #[derive(Resource, Default)]
 pub struct Pause(pub bool);
 
+/// Simulated seconds since the start, derived from the steps counted by [`StepClock`]
+#[derive(Resource, Reflect, Default)]
+pub struct SimTime(pub f64);
+
+/// Counts the steps since the speed or the substeps last changed. The time is computed from the count instead of summing up the steps,
+/// so it doesn't collect a rounding error every tick and a run with the same changes of the speed always reaches the same times.
+#[derive(Resource, Debug, Default, Clone, Copy)]
+pub struct StepClock {
+    /// Simulated time at the start of the current segment
+    pub segment_start: f64,
+    pub step_size: f64,
+    pub sub_steps: i32,
+    pub steps: u64,
+}
+
+impl StepClock {
+
+    pub fn time(&self) -> f64 {
+        self.segment_start + self.steps as f64 * self.step_size
+    }
+
+    /// Starts a new segment at the current time if the step size or the substeps changed
+    pub fn set_rate(&mut self, step_size: f64, sub_steps: i32) {
+        if step_size != self.step_size || sub_steps != self.sub_steps {
+            *self = StepClock { segment_start: self.time(), step_size, sub_steps, steps: 0 };
+        }
+    }
+
+    /// Starts a new segment at `time`, after a step that was cut short
+    pub fn restart_at(&mut self, time: f64) {
+        self.segment_start = time;
+        self.steps = 0;
+    }
+
+}
+
+impl SimTime {
+
+    pub fn date(&self, starting_time: &StartingTime) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp_millis(starting_time.0 + (self.0 * 1000.0) as i64).unwrap()
+    }
+
+}
+
 #[derive(Resource)]
 pub struct SubSteps(pub i32);
 
@@ -71,154 +135,803 @@ impl SubSteps {
       
 }
 
+/// The numerical scheme used to advance positions and velocities by one substep.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    /// Kick then drift, first order. This is what the simulation always used.
+    #[default]
+    SemiImplicitEuler,
+    /// Kick-drift-kick leapfrog, second order and symplectic.
+    VelocityVerlet,
+    /// Fourth order symplectic scheme (Yoshida / Forest-Ruth), three force evaluations per substep.
+    Yoshida4,
+    /// Embedded Runge-Kutta 5(4) that picks its own step size from [`AdaptiveStep::tolerance`].
+    /// `Speed` * `SubSteps` is then only the simulated time per frame.
+    DormandPrince,
+}
+
+impl Integrator {
+
+    pub const ALL: [Integrator; 4] = [Integrator::SemiImplicitEuler, Integrator::VelocityVerlet, Integrator::Yoshida4, Integrator::DormandPrince];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrator::SemiImplicitEuler => "Semi-implicit Euler",
+            Integrator::VelocityVerlet => "Velocity Verlet",
+            Integrator::Yoshida4 => "Yoshida (4th order)",
+            Integrator::DormandPrince => "Dormand-Prince (adaptive)",
+        }
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        *self == Integrator::DormandPrince
+    }
+
+}
+
+/// Decides which pairs of bodies attract each other, can be set per scenario in the .sim file
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionMode {
+    /// Stars attract everything and bodies attract their direct children
+    #[default]
+    Hierarchical,
+    /// Every body attracts every other body
+    Full,
+}
+
+impl InteractionMode {
+
+    pub const ALL: [InteractionMode; 2] = [InteractionMode::Hierarchical, InteractionMode::Full];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InteractionMode::Hierarchical => "Hierarchical",
+            InteractionMode::Full => "Full N-body",
+        }
+    }
+
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Solver {
+    /// Sums up every interacting pair
+    #[default]
+    Direct,
+    /// Approximates far away groups of bodies by their center of mass, only used in the full N-body mode
+    BarnesHut,
+}
+
+impl Solver {
+
+    pub const ALL: [Solver; 2] = [Solver::Direct, Solver::BarnesHut];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Solver::Direct => "Direct",
+            Solver::BarnesHut => "Barnes-Hut",
+        }
+    }
+
+}
+
+/// Everything that changes how the gravitational acceleration is calculated
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct GravitySettings {
+    pub interaction_mode: InteractionMode,
+    pub solver: Solver,
+    /// Opening angle of the Barnes-Hut solver, smaller is more accurate
+    pub theta: f64,
+    /// Spreads the force calculation over the compute task pool once there are enough bodies
+    pub parallel: bool,
+    /// Plummer softening length in meters, keeps the force finite when bodies get very close. Can be set per scenario.
+    pub softening: f64,
+    /// Adds the 1PN Schwarzschild correction for bodies orbiting a star. Can be set per scenario.
+    pub relativity: bool,
+}
+
+impl Default for GravitySettings {
+
+    fn default() -> Self {
+        GravitySettings {
+            interaction_mode: InteractionMode::default(),
+            solver: Solver::default(),
+            theta: DEFAULT_THETA,
+            parallel: true,
+            softening: 0.0,
+            relativity: false,
+        }
+    }
+
+}
+
 pub const NBODY_TOTAL_TIME: DiagnosticId =
     DiagnosticId::from_u128(337040787172757619024841343456040760896);
     
 pub const NBODY_STEP_TIME: DiagnosticId =
     DiagnosticId::from_u128(337040787171757619024831343456040760892);
 
+const PARALLEL_MIN_BODIES: usize = 64; //below this, spawning the tasks takes longer than the calculation itself
+
+//coefficients of the 4th order Yoshida integrator
+const CBRT_2: f64 = 1.2599210498948732;
+const YOSHIDA_W0: f64 = -CBRT_2 / (2.0 - CBRT_2);
+const YOSHIDA_W1: f64 = 1.0 / (2.0 - CBRT_2);
+const YOSHIDA_KICKS: [f64; 4] = [YOSHIDA_W1 / 2.0, (YOSHIDA_W0 + YOSHIDA_W1) / 2.0, (YOSHIDA_W0 + YOSHIDA_W1) / 2.0, YOSHIDA_W1 / 2.0];
+const YOSHIDA_DRIFTS: [f64; 3] = [YOSHIDA_W1, YOSHIDA_W0, YOSHIDA_W1];
+
+/// Plain copy of a body's physical state, so the integrators don't have to work on the ECS query directly.
+#[derive(Debug, Clone)]
+pub struct PhysicsBody {
+    pub entity: Entity,
+    pub mass: f64,
+    pub position: DVec3,
+    pub velocity: DVec3,
+    pub acceleration: DVec3,
+    /// Physical radius in meters, used for collisions
+    pub radius: f64,
+    pub is_star: bool,
+    pub parent: Option<usize>,
+    /// J2 data and the normalized spin axis, only for flattened bodies whose tilt was already applied
+    pub oblateness: Option<(Oblateness, DVec3)>,
+}
+
+impl PhysicsBody {
+
+    pub fn new(entity: Entity, mass: &Mass, diameter: &Diameter, tilt: &AxialTilt, oblateness: Option<&Oblateness>, acceleration: &Acceleration, velocity: &Velocity, position: &SimPosition, is_star: bool) -> Self {
+        PhysicsBody {
+            entity,
+            mass: mass.0,
+            position: position.0,
+            velocity: velocity.0,
+            acceleration: acceleration.0,
+            radius: diameter.num as f64 / M_TO_UNIT / 2.0,
+            is_star,
+            parent: None,
+            oblateness: oblateness.copied().zip(tilt.axis.map(|axis| axis.as_dvec3().normalize())),
+        }
+    }
+
+    /// A point mass without parent for the tests, the other fields can be changed with struct update syntax
+    #[cfg(test)]
+    pub fn point(index: u32, mass: f64, position: DVec3, velocity: DVec3) -> Self {
+        PhysicsBody {
+            entity: Entity::from_raw(index),
+            mass,
+            position,
+            velocity,
+            acceleration: DVec3::ZERO,
+            radius: 0.0,
+            is_star: false,
+            parent: None,
+            oblateness: None,
+        }
+    }
+
+}
+
+/// Runs in [`FixedUpdate`], so every tick advances the simulation by the same amount no matter the frame rate.
 pub fn apply_physics(
-    mut query: Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform, Has<Star>, Has<Planet>, Option<&BodyChildren>)>,
-    pause: Res<Pause>,
-    time: Res<Time>,
+    mut query: Query<(Entity, &Mass, &Diameter, &AxialTilt, Option<&Oblateness>, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, Has<Star>, Option<&BodyParent>)>,
+    mut pause: ResMut<Pause>,
+    time: Res<Time<Fixed>>,
     speed: Res<Speed>,
-    selected_entity: Res<SelectedEntity>,
-    mut orbit_offset: ResMut<OrbitOffset>,
+    mut sim_time: ResMut<SimTime>,
+    mut clock: ResMut<StepClock>,
     sub_steps: Res<SubSteps>,
+    integrator: Res<Integrator>,
+    gravity: Res<GravitySettings>,
+    mut adaptive: ResMut<AdaptiveStep>,
     mut nbody_stats: ResMut<NBodyStats>,
-    mut diagnostics: Diagnostics
+    mut collision: CollisionState,
+    mut watchdog: ResMut<Watchdog>,
+    mut diagnostics: Diagnostics,
+    mut particles: Query<(Entity, &mut SimPosition, &mut Velocity, &mut Acceleration, Option<&BodyParent>), (With<TestParticle>, Without<Mass>)>,
+    mut maneuvers: Query<(Entity, &mut ManeuverSchedule)>,
 ) {
     if pause.0 {
-        change_selection_without_update(&mut query, &selected_entity, &mut orbit_offset); //allows switching bodies while paused    
         return;
     }
-    let delta = time.delta_seconds() as f64;
+    let delta = time.timestep().as_secs_f64() * speed.0;
+    clock.set_rate(delta, sub_steps.0);
+    let start_clock = *clock;
+    let start_touching = collision.log.touching.clone();
     let start = Instant::now();
     nbody_stats.steps = 0;
-    for _ in 0..sub_steps.0 - 1 {
-        update_acceleration(&mut query, &mut nbody_stats.steps);
-        update_velocity_and_positions(&mut query, delta, &speed, &selected_entity, &mut orbit_offset, false);
-    }
-    let start_step = Instant::now();            
-    update_acceleration(&mut query, &mut nbody_stats.steps);
-    update_velocity_and_positions(&mut query, delta, &speed, &selected_entity, &mut orbit_offset, true);
-    diagnostics.add_measurement(NBODY_STEP_TIME, || start_step.elapsed().as_nanos() as f64);                
+    let mut bodies = collect_bodies(&query, &particles);
+    update_acceleration(&mut bodies, &gravity, &mut nbody_stats.steps); //every integrator expects the accelerations of the current positions
+    let mut schedules = maneuvers.iter().filter(|(_, schedule)| schedule.upcoming().next().is_some()).map(|(entity, schedule)| (entity, schedule.clone())).collect::<Vec<_>>();
+    let mut collisions = vec![];
+    let mut failure = None;
+    if integrator.is_adaptive() {
+        let start_adaptive = Instant::now();
+        let span = delta * sub_steps.0 as f64;
+        let mut collision_steps = 0;
+        let covered = integrate_with_burns(&mut bodies, &mut schedules, sim_time.0, span, |bodies, time, duration| {
+            //every accepted step is checked, stopping right at the step of a failure or a collision that pauses
+            let result = integrate_adaptive(bodies, &gravity, time, duration, &mut adaptive, &mut nbody_stats.steps, |bodies, time| {
+                let stop = resolve_collisions(bodies, collision.settings.mode, &mut collision.log, &gravity, time, &mut collisions, &mut collision_steps);
+                pause.0 |= stop;
+                stop
+            });
+            result.unwrap_or_else(|error| {
+                failure = Some(error);
+                0.0
+            })
+        });
+        nbody_stats.steps += collision_steps;
+        //a full tick counts as its substeps, a tick that ran out of attempts or stopped at a collision starts a new segment
+        if covered == span {
+            clock.steps += sub_steps.0 as u64;
+        } else {
+            clock.restart_at(sim_time.0 + covered);
+        }
+        sim_time.0 = clock.time();
+        let attempts = (adaptive.accepted_steps + adaptive.rejected_steps).max(1);
+        diagnostics.add_measurement(NBODY_STEP_TIME, || start_adaptive.elapsed().as_nanos() as f64 / attempts as f64);
+    } else {
+        for step in 0..sub_steps.0 {
+            let start_step = Instant::now();
+            //burns can split the substep, so they happen at their exact time even if a tick spans hours
+            integrate_with_burns(&mut bodies, &mut schedules, sim_time.0, delta, |bodies, _, delta| {
+                integrate(bodies, *integrator, &gravity, delta, &mut nbody_stats.steps);
+                delta
+            });
+            clock.steps += 1;
+            sim_time.0 = clock.time();
+            if step == sub_steps.0 - 1 {
+                diagnostics.add_measurement(NBODY_STEP_TIME, || start_step.elapsed().as_nanos() as f64);
+            }
+            failure = find_non_finite(&bodies, sim_time.0);
+            if failure.is_some() {
+                break;
+            }
+            if resolve_collisions(&mut bodies, collision.settings.mode, &mut collision.log, &gravity, sim_time.0, &mut collisions, &mut nbody_stats.steps) {
+                pause.0 = true; //stop right at the step of the collision
+                break;
+            }
+        }
+    }
+    if let Some(failure) = failure {
+        error!("{:?} has a non-finite state (position {}, velocity {}), pausing the simulation", failure.entity, failure.position, failure.velocity);
+        watchdog.failure = Some(failure);
+        pause.0 = true;
+        //nothing gets written back, so the bodies stay at the state of the last tick
+        *clock = start_clock;
+        sim_time.0 = clock.time();
+        collision.log.touching = start_touching;
+        return;
+    }
+    collision.events.send_batch(collisions);
+    write_bodies(&mut query, &mut particles, &bodies);
+    for (entity, schedule) in schedules {
+        if let Ok((_, mut current)) = maneuvers.get_mut(entity) {
+            *current = schedule;
+        }
+    }
     diagnostics.add_measurement(NBODY_TOTAL_TIME, || start.elapsed().as_nanos() as f64);
 }
 
-fn update_acceleration(
-    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform, Has<Star>, Has<Planet>, Option<&BodyChildren>)>,
+fn collect_bodies(
+    query: &Query<(Entity, &Mass, &Diameter, &AxialTilt, Option<&Oblateness>, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, Has<Star>, Option<&BodyParent>)>,
+    particles: &Query<(Entity, &mut SimPosition, &mut Velocity, &mut Acceleration, Option<&BodyParent>), (With<TestParticle>, Without<Mass>)>,
+) -> Vec<PhysicsBody> {
+    let bodies = query.iter().map(|(entity, mass, diameter, tilt, oblateness, acc, _, vel, sim_pos, is_star, parent)| {
+        (PhysicsBody::new(entity, mass, diameter, tilt, oblateness, acc, vel, sim_pos, is_star), parent)
+    });
+    let particles = particles.iter().map(|(entity, sim_pos, vel, acc, parent)| {
+        (PhysicsBody {
+            entity,
+            mass: 0.0,
+            position: sim_pos.0,
+            velocity: vel.0,
+            acceleration: acc.0,
+            radius: 0.0,
+            is_star: false,
+            parent: None,
+            oblateness: None,
+        }, parent)
+    });
+    link_parents(bodies.chain(particles).collect())
+}
+
+/// Sorts the bodies like the integrators expect them and turns the parent entities into indices
+pub fn link_parents(mut bodies: Vec<(PhysicsBody, Option<&BodyParent>)>) -> Vec<PhysicsBody> {
+    //the massless bodies go last, so the gravity only has to sum up the pairs of the massive ones in front of them
+    bodies.sort_by_key(|(body, _)| body.mass == 0.0);
+    let indices: HashMap<Entity, usize> = bodies.iter().enumerate().map(|(index, (body, _))| (body.entity, index)).collect();
+    bodies.into_iter().map(|(mut body, parent)| {
+        body.parent = parent.and_then(|p| indices.get(&p.0).copied());
+        body
+    }).collect()
+}
+
+/// Advances all bodies by one substep. The accelerations have to match the current positions when calling this and will match the new positions afterwards.
+pub fn integrate(
+    bodies: &mut [PhysicsBody],
+    integrator: Integrator,
+    gravity: &GravitySettings,
+    delta: f64,
     steps: &mut i32,
 ) {
-    let mut other_bodies: Vec<(Entity, &Mass, Mut<Acceleration>, Mut<SimPosition>, bool, bool, Option<&BodyChildren>)> = Vec::with_capacity(query.iter().count());
-    for (entity, mass, mut acc, _, _, sim_pos, _, is_star, is_planet, children) in query.iter_mut() {
-        acc.0 = DVec3::ZERO;
-        for (other_entity, other_mass, ref mut other_acc, other_sim_pos, other_is_star, other_is_planet, other_children) in other_bodies.iter_mut() {
-            if (is_star && *other_is_planet) || (is_planet && *other_is_star) || (!is_star && !is_planet && (other_children.is_some() && other_children.unwrap().0.contains(&entity)) || *other_is_star) || (!*other_is_star && !*other_is_planet && (children.is_some() && children.unwrap().0.contains(other_entity) || is_star)) { 
-                let distance = other_sim_pos.0 - sim_pos.0;
-                let r_sq = distance.length_squared();
-                let force_direction = distance.normalize(); // Calculate the direction vector  
-                let force_magnitude = G * mass.0 * other_mass.0 / r_sq;
-                let force = force_direction * force_magnitude;
-                acc.0 += force;
-                other_acc.0 -= force;
-                *steps += 1;
+    match integrator {
+        Integrator::SemiImplicitEuler => {
+            kick(bodies, delta);
+            drift(bodies, delta);
+            update_acceleration(bodies, gravity, steps);
+        }
+        Integrator::VelocityVerlet => {
+            kick(bodies, delta / 2.0);
+            drift(bodies, delta);
+            update_acceleration(bodies, gravity, steps);
+            kick(bodies, delta / 2.0);
+        }
+        Integrator::Yoshida4 => {
+            for (kick_c, drift_c) in YOSHIDA_KICKS.iter().zip(YOSHIDA_DRIFTS.iter()) {
+                kick(bodies, delta * kick_c);
+                drift(bodies, delta * drift_c);
+                update_acceleration(bodies, gravity, steps);
             }
+            kick(bodies, delta * YOSHIDA_KICKS[3]);
         }
-        other_bodies.push((entity, mass, acc, sim_pos, is_star, is_planet, children));
+        Integrator::DormandPrince => fixed_step(bodies, gravity, delta, steps),
+    }
+}
+
+fn kick(bodies: &mut [PhysicsBody], delta: f64) {
+    for body in bodies.iter_mut() {
+        body.velocity += body.acceleration * delta;
+    }
+}
+
+fn drift(bodies: &mut [PhysicsBody], delta: f64) {
+    for body in bodies.iter_mut() {
+        body.position += body.velocity * delta;
     }
 }
 
-fn change_selection_without_update(
-    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform, Has<Star>, Has<Planet>, Option<&BodyChildren>)>,
-    selected_entity: &Res<SelectedEntity>,
-    orbit_offset: &mut ResMut<OrbitOffset>,
+/// In the hierarchical mode, pairs only attract each other if one of them is a star or one is the other's parent
+fn interacts(bodies: &[PhysicsBody], mode: InteractionMode, first: usize, second: usize) -> bool {
+    match mode {
+        InteractionMode::Full => true,
+        InteractionMode::Hierarchical => bodies[first].is_star || bodies[second].is_star || bodies[first].parent == Some(second) || bodies[second].parent == Some(first)
+    }
+}
+
+pub fn update_acceleration(
+    bodies: &mut [PhysicsBody],
+    gravity: &GravitySettings,
+    steps: &mut i32,
 ) {
-    let offset = match selected_entity.entity { //if orbit_offset.enabled is true, we calculate the new position of the selected entity first and then move it to 0,0,0 and add the actual position to all other bodies
-        Some(selected) => {
-            if !orbit_offset.enabled {
-                DVec3::ZERO
-            } else if let Ok((_, _, _, _, _, sim_pos, mut transform, _, _, _)) = query.get_mut(selected) {
-                let raw_translation = sim_pos.0 * M_TO_UNIT;
-                transform.translation = Vec3::ZERO; //the selected entity will always be at 0,0,0
-                -raw_translation 
-            } else {
-                DVec3::ZERO 
-            }
-        }
-        None => DVec3::ZERO,
+    //the massless bodies at the end only need the forces of the massive ones, see collect_bodies
+    let massive = bodies.len() - bodies.iter().rev().take_while(|body| body.mass == 0.0).count();
+    let parallel = gravity.parallel && massive >= PARALLEL_MIN_BODIES;
+    let softening_sq = gravity.softening * gravity.softening;
+    if gravity.interaction_mode == InteractionMode::Full && gravity.solver == Solver::BarnesHut {
+        barnes_hut::update_acceleration(&mut bodies[..massive], gravity.theta, softening_sq, parallel, steps);
+    } else {
+        update_acceleration_direct(&mut bodies[..massive], gravity.interaction_mode, softening_sq, parallel, steps);
+    }
+    update_test_particles(bodies, massive, gravity.interaction_mode, softening_sq, gravity.parallel, steps);
+    add_oblateness(bodies);
+    if gravity.relativity {
+        add_relativistic_correction(bodies);
+    }
+}
+
+/// Test particle fast path, costs O(massive bodies * particles) instead of growing with the square of all bodies
+fn update_test_particles(
+    bodies: &mut [PhysicsBody],
+    massive: usize,
+    mode: InteractionMode,
+    softening_sq: f64,
+    parallel: bool,
+    steps: &mut i32,
+) {
+    let count = bodies.len() - massive;
+    let accelerations = if parallel && count >= PARALLEL_MIN_BODIES {
+        let bodies = &*bodies;
+        par_map(count, steps, |index, steps| {
+            *steps += massive as i32;
+            test_particle_acceleration(bodies, massive, mode, massive + index, softening_sq)
+        })
+    } else {
+        *steps += (count * massive) as i32;
+        (massive..bodies.len()).map(|index| test_particle_acceleration(bodies, massive, mode, index, softening_sq)).collect()
     };
-    if offset.as_vec3() == orbit_offset.value {
-        return;
+    for (body, acceleration) in bodies[massive..].iter_mut().zip(accelerations) {
+        body.acceleration = acceleration;
     }
-    for (entity, _, _, _, _, sim_pos, mut transform, _, _, _) in query.iter_mut() {
-        if orbit_offset.enabled {
-            if let Some(s_entity) = selected_entity.entity {
-                if s_entity == entity {
+}
+
+fn update_acceleration_direct(
+    bodies: &mut [PhysicsBody],
+    mode: InteractionMode,
+    softening_sq: f64,
+    parallel: bool,
+    steps: &mut i32,
+) {
+    if parallel {
+        //every task sums up the forces of its bodies in the same order as the serial loop below, so both give the exact same result
+        let forces = par_map(bodies.len(), steps, |i, steps| {
+            let mut force = DVec3::ZERO;
+            for j in 0..bodies.len() {
+                if j == i || !exerts_force(bodies, mode, i, j) {
                     continue;
                 }
+                if j < i {
+                    force -= pair_force(bodies, j, i, softening_sq);
+                } else {
+                    force += pair_force(bodies, i, j, softening_sq);
+                    *steps += 1;
+                }
             }
+            force
+        });
+        for (body, force) in bodies.iter_mut().zip(forces) {
+            body.acceleration = force;
         }
-        let pos_without_offset = sim_pos.0.as_vec3() * M_TO_UNIT as f32;
-        transform.translation = pos_without_offset + offset.as_vec3(); //apply offset   
-    }
-    if orbit_offset.enabled {
-        orbit_offset.value = offset.as_vec3();   
     } else {
-        orbit_offset.value = Vec3::ZERO
+        for body in bodies.iter_mut() {
+            body.acceleration = DVec3::ZERO;
+        }
+        for i in 0..bodies.len() {
+            for j in (i + 1)..bodies.len() {
+                if !exerts_force(bodies, mode, i, j) {
+                    continue;
+                }
+                let force = pair_force(bodies, i, j, softening_sq);
+                bodies[i].acceleration += force;
+                bodies[j].acceleration -= force;
+                *steps += 1;
+            }
+        }
+    }
+    for i in 0..bodies.len() {
+        bodies[i].acceleration = if bodies[i].mass > 0.0 {
+            bodies[i].acceleration / bodies[i].mass //actually apply the force to the body
+        } else {
+            test_particle_acceleration(bodies, bodies.len(), mode, i, softening_sq)
+        };
+    }
+}
+
+/// Pairs with a massless test particle are left out of the forces, the particles get their acceleration directly
+fn exerts_force(bodies: &[PhysicsBody], mode: InteractionMode, first: usize, second: usize) -> bool {
+    bodies[first].mass > 0.0 && bodies[second].mass > 0.0 && interacts(bodies, mode, first, second)
+}
+
+/// Massless bodies feel the gravity of every massive body they interact with, but don't attract anything themselves.
+/// Only the first `massive` bodies are checked.
+fn test_particle_acceleration(bodies: &[PhysicsBody], massive: usize, mode: InteractionMode, index: usize, softening_sq: f64) -> DVec3 {
+    let mut acceleration = DVec3::ZERO;
+    for other in 0..massive {
+        if other != index && bodies[other].mass > 0.0 && interacts(bodies, mode, index, other) {
+            acceleration += barnes_hut::pull(bodies[index].position, bodies[other].position, bodies[other].mass, softening_sq);
+        }
+    }
+    acceleration
+}
+
+/// J2 term of a flattened parent acting on its children, with k being the parent's spin axis and z = r · k:
+/// a = 3 J2 GM R² / (2 r⁵) * ((5 z² / r² - 1) r - 2 z k). The parent is pulled back so the momentum stays conserved.
+fn add_oblateness(bodies: &mut [PhysicsBody]) {
+    for i in 0..bodies.len() {
+        let Some(parent) = bodies[i].parent else {
+            continue;
+        };
+        let Some((oblateness, axis)) = bodies[parent].oblateness else {
+            continue;
+        };
+        let r = bodies[i].position - bodies[parent].position;
+        let r_sq = r.length_squared();
+        let z = r.dot(axis);
+        let factor = 1.5 * oblateness.j2 * G * bodies[parent].mass * oblateness.equatorial_radius.powi(2) / (r_sq * r_sq * r_sq.sqrt());
+        let acceleration = (r * (5.0 * z * z / r_sq - 1.0) - axis * (2.0 * z)) * factor;
+        bodies[i].acceleration += acceleration;
+        //a massless parent can't be pulled back, its own field is zero anyway
+        if bodies[parent].mass > 0.0 {
+            bodies[parent].acceleration -= acceleration * (bodies[i].mass / bodies[parent].mass);
+        }
+    }
+}
+
+/// Schwarzschild 1PN term for every body whose parent is a star, treating the body as a test particle around it:
+/// a = GM / (c² r³) * ((4GM / r - v²) r + 4 (r · v) v), with r and v relative to the star.
+/// This is what makes Mercury's perihelion precess by the extra 43″ per century.
+fn add_relativistic_correction(bodies: &mut [PhysicsBody]) {
+    for i in 0..bodies.len() {
+        let Some(parent) = bodies[i].parent else {
+            continue;
+        };
+        if !bodies[parent].is_star {
+            continue;
+        }
+        let r = bodies[i].position - bodies[parent].position;
+        let v = bodies[i].velocity - bodies[parent].velocity;
+        let gm = G * (bodies[parent].mass + bodies[i].mass);
+        let distance = r.length();
+        let correction = r * (4.0 * gm / distance - v.length_squared()) + v * (4.0 * r.dot(v));
+        bodies[i].acceleration += correction * (gm / (C * C * distance.powi(3)));
+    }
+}
+
+/// Gravitational force that `second` exerts on `first`, Plummer-softened by `softening_sq` (ε²)
+fn pair_force(bodies: &[PhysicsBody], first: usize, second: usize, softening_sq: f64) -> DVec3 {
+    let distance = bodies[second].position - bodies[first].position;
+    let r_sq = distance.length_squared() + softening_sq;
+    if r_sq == 0.0 {
+        return DVec3::ZERO; //bodies at the same position without softening have no direction to pull in
+    }
+    let force_magnitude = G * bodies[first].mass * bodies[second].mass / (r_sq * r_sq.sqrt()); //divided by r³ because distance isn't normalized
+    distance * force_magnitude
+}
+
+/// Calls `f` for every index in `0..len` on the compute task pool and returns the results in the same order
+pub fn par_map<T: Send + 'static>(
+    len: usize,
+    steps: &mut i32,
+    f: impl Fn(usize, &mut i32) -> T + Sync,
+) -> Vec<T> {
+    let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+    let chunk_size = (len / pool.thread_num().max(1)).max(1);
+    let f = &f;
+    let chunks = pool.scope(|scope| {
+        for start in (0..len).step_by(chunk_size) {
+            scope.spawn(async move {
+                let mut chunk_steps = 0;
+                let results: Vec<T> = (start..(start + chunk_size).min(len)).map(|i| f(i, &mut chunk_steps)).collect();
+                (results, chunk_steps)
+            });
+        }
+    });
+    let mut results = Vec::with_capacity(len);
+    for (chunk, chunk_steps) in chunks {
+        results.extend(chunk);
+        *steps += chunk_steps;
     }
+    results
 }
 
-fn update_velocity_and_positions(
-    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform, Has<Star>, Has<Planet>, Option<&BodyChildren>)>,
-    delta_time: f64,
-    speed: &Res<Speed>,
-    selected_entity: &Res<SelectedEntity>,
-    orbit_offset: &mut ResMut<OrbitOffset>,
-    last_step: bool,
+fn write_bodies(
+    query: &mut Query<(Entity, &Mass, &Diameter, &AxialTilt, Option<&Oblateness>, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, Has<Star>, Option<&BodyParent>)>,
+    particles: &mut Query<(Entity, &mut SimPosition, &mut Velocity, &mut Acceleration, Option<&BodyParent>), (With<TestParticle>, Without<Mass>)>,
+    bodies: &[PhysicsBody],
 ) {
-    let offset = match selected_entity.entity { //if orbit_offset.enabled is true, we calculate the new position of the selected entity first and then move it to 0,0,0 and add the actual position to all other bodies
-        Some(selected) => {
-            if !orbit_offset.enabled || !last_step {
-                DVec3::ZERO
-            } else if let Ok((_, mass, mut acc, mut orbit_s, mut vel, mut sim_pos, mut transform, _, _, _)) = query.get_mut(selected) {
-                if last_step {
-                    orbit_s.force_direction = acc.0.normalize();
-                }
-                acc.0 /= mass.0; //actually apply the force to the body
-                vel.0 += acc.0 * delta_time * speed.0;
-                sim_pos.0 += vel.0 * delta_time * speed.0; //this is the same step as below, but we are doing this first for the offset
-                let raw_translation = sim_pos.0 * M_TO_UNIT;
-                transform.translation = Vec3::ZERO; //the selected entity will always be at 0,0,0
-                -raw_translation 
+    for body in bodies {
+        if let Ok((_, _, _, _, _, mut acc, mut orbit_s, mut vel, mut sim_pos, _, _)) = query.get_mut(body.entity) {
+            acc.0 = body.acceleration;
+            orbit_s.force_direction = body.acceleration.normalize();
+            vel.0 = body.velocity;
+            sim_pos.0 = body.position;
+        } else if let Ok((_, mut sim_pos, mut vel, mut acc, _)) = particles.get_mut(body.entity) {
+            acc.0 = body.acceleration;
+            vel.0 = body.velocity;
+            sim_pos.0 = body.position;
+        }
+    }
+}
+
+/// Moves the rendered bodies to their simulated positions every frame, this also allows switching bodies while paused
+pub fn update_translations(
+    mut query: Query<(Entity, &SimPosition, &mut Transform)>,
+    selected_entity: Res<SelectedEntity>,
+    mut orbit_offset: ResMut<OrbitOffset>,
+) {
+    let offset = match selected_entity.entity { //if orbit_offset.enabled is true, we move the selected entity to 0,0,0 and add the actual position to all other bodies
+        Some(selected) if orbit_offset.enabled => {
+            if let Ok((_, sim_pos, _)) = query.get(selected) {
+                -(sim_pos.0 * M_TO_UNIT)
             } else {
-                DVec3::ZERO 
+                DVec3::ZERO
             }
         }
-        None => DVec3::ZERO,
+        _ => DVec3::ZERO,
     };
-    for (entity, mass, mut acc, mut orbit_s, mut vel, mut sim_pos, mut transform, _, _, _) in query.iter_mut() {
-        if orbit_offset.enabled && last_step {
-            if let Some(s_entity) = selected_entity.entity {
-                if s_entity == entity {
-                    continue;
-                }
-            }
+    for (entity, sim_pos, mut transform) in query.iter_mut() {
+        if orbit_offset.enabled && selected_entity.entity == Some(entity) {
+            transform.translation = Vec3::ZERO; //the selected entity will always be at 0,0,0
+            continue;
+        }
+        let pos_without_offset = sim_pos.0.as_vec3() * M_TO_UNIT as f32;
+        transform.translation = pos_without_offset + offset.as_vec3(); //apply offset
+    }
+    orbit_offset.value = offset.as_vec3();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use bevy::diagnostic::DiagnosticsStore;
+    use bevy::ecs::event::Events;
+    use bevy::prelude::{Schedule, Virtual, World};
+    use bevy::time::run_fixed_update_schedule;
+
+    use crate::collision::{Collision, CollisionLog, CollisionSettings};
+
+    use super::*;
+
+    /// Runs the physics of a planet around a star like the app does, with `frames` frames that are `ticks` physics ticks long each.
+    /// Returns the simulated time and the position of the planet.
+    fn run_frames(frames: u32, ticks: u32) -> (f64, DVec3) {
+        let mut world = World::new();
+        let body = |mass: f64, position: DVec3, velocity: DVec3| {
+            (Mass(mass), Diameter::default(), AxialTilt::default(), Acceleration::default(), OrbitSettings::default(), Velocity(velocity), SimPosition(position))
+        };
+        let star = world.spawn((body(2.0e30, DVec3::ZERO, DVec3::ZERO), Star::default())).id();
+        let planet = world.spawn((body(6.0e24, DVec3::new(1.5e11, 0.0, 0.0), DVec3::new(0.0, 29_780.0, 0.0)), BodyParent(star))).id();
+        world.insert_resource(Speed(1000.0));
+        world.insert_resource(SubSteps(DEFAULT_SUB_STEPS));
+        world.insert_resource(Time::<Fixed>::from_hz(PHYSICS_TICK_RATE));
+        world.init_resource::<Time<Virtual>>();
+        world.init_resource::<Time>();
+        world.insert_resource(Integrator::Yoshida4);
+        world.insert_resource(Events::<Collision>::default());
+        world.init_resource::<GravitySettings>();
+        world.init_resource::<Pause>();
+        world.init_resource::<SimTime>();
+        world.init_resource::<StepClock>();
+        world.init_resource::<AdaptiveStep>();
+        world.init_resource::<NBodyStats>();
+        world.init_resource::<CollisionSettings>();
+        world.init_resource::<CollisionLog>();
+        world.init_resource::<Watchdog>();
+        world.init_resource::<DiagnosticsStore>();
+        let mut schedule = Schedule::new(FixedUpdate);
+        schedule.add_systems(apply_physics);
+        world.add_schedule(schedule);
+        let frame = world.resource::<Time<Fixed>>().timestep() * ticks;
+        for _ in 0..frames {
+            world.resource_mut::<Time<Virtual>>().advance_by(frame);
+            run_fixed_update_schedule(&mut world);
         }
-        if last_step {
-            orbit_s.force_direction = acc.0.normalize();
+        (world.resource::<SimTime>().0, world.get::<SimPosition>(planet).unwrap().0)
+    }
+
+    #[test]
+    fn slow_frames_take_more_steps_of_the_same_size() {
+        let smooth = run_frames(120, 1);
+        //every frame takes as long as three ticks, like with a third of the frame rate
+        let slow = run_frames(40, 3);
+        assert_eq!(smooth, slow);
+        let step = Time::<Fixed>::from_hz(PHYSICS_TICK_RATE).timestep().as_secs_f64() * 1000.0;
+        assert_eq!(smooth.0, (120 * DEFAULT_SUB_STEPS) as f64 * step);
+    }
+
+    #[test]
+    fn step_clock_counts_whole_steps_per_segment() {
+        let mut clock = StepClock::default();
+        clock.set_rate(0.1, 4);
+        clock.steps += 1_000_000;
+        let mut sum = 0.0;
+        for _ in 0..1_000_000 {
+            sum += 0.1;
         }
-        acc.0 /= mass.0; //actually apply the force to the body
-        vel.0 += acc.0 * delta_time * speed.0;
-        sim_pos.0 += vel.0 * delta_time * speed.0;
-        if last_step {
-            let pos_without_offset = sim_pos.0.as_vec3() * M_TO_UNIT as f32;
-            transform.translation = pos_without_offset + offset.as_vec3(); //apply offset   
+        assert_eq!(clock.time(), 100_000.0);
+        assert_ne!(sum, clock.time()); //the running sum drifts
+
+        //the same rate keeps the segment, a new one starts where the old one ended
+        clock.set_rate(0.1, 4);
+        assert_eq!(clock.steps, 1_000_000);
+        clock.set_rate(0.1, 8);
+        assert_eq!((clock.segment_start, clock.steps), (100_000.0, 0));
+        clock.set_rate(60.0, 8);
+        clock.steps += 10;
+        assert_eq!(clock.time(), 100_600.0);
+
+        clock.restart_at(100_630.0);
+        clock.steps += 1;
+        assert_eq!(clock.time(), 100_690.0);
+    }
+
+    /// Distance from the start after one period of an eccentric orbit around a star, and the largest relative change of the energy on the way
+    fn orbit_errors(integrator: Integrator, steps_per_orbit: usize) -> (f64, f64) {
+        let (mass, a, e) = (2.0e30, 1.5e11, 0.5);
+        let periapsis = a * (1.0 - e);
+        let start = DVec3::new(periapsis, 0.0, 0.0);
+        let speed = (G * mass * (1.0 + e) / periapsis).sqrt();
+        let mut bodies = vec![
+            PhysicsBody { is_star: true, ..PhysicsBody::point(0, mass, DVec3::ZERO, DVec3::ZERO) },
+            PhysicsBody { parent: Some(0), ..PhysicsBody::point(1, 0.0, start, DVec3::new(0.0, speed, 0.0)) },
+        ];
+        let energy = |body: &PhysicsBody| body.velocity.length_squared() / 2.0 - G * mass / body.position.length();
+        let initial = energy(&bodies[1]);
+        let gravity = GravitySettings::default();
+        let delta = TAU * (a.powi(3) / (G * mass)).sqrt() / steps_per_orbit as f64;
+        let mut worst = 0.0f64;
+        update_acceleration(&mut bodies, &gravity, &mut 0);
+        for _ in 0..steps_per_orbit {
+            integrate(&mut bodies, integrator, &gravity, delta, &mut 0);
+            worst = worst.max(((energy(&bodies[1]) - initial) / initial).abs());
         }
+        (bodies[1].position.distance(start), worst)
     }
-    if orbit_offset.enabled && last_step {
-        orbit_offset.value = offset.as_vec3();   
-    } else {
-        orbit_offset.value = Vec3::ZERO
+
+    #[test]
+    fn integrators_converge_with_their_order() {
+        //halving the step shrinks the energy error by two to the power of the order
+        for (integrator, order) in [(Integrator::SemiImplicitEuler, 1.0), (Integrator::VelocityVerlet, 2.0), (Integrator::Yoshida4, 4.0)] {
+            let (_, coarse) = orbit_errors(integrator, 500);
+            let (_, fine) = orbit_errors(integrator, 1000);
+            assert!(((coarse / fine).log2() - order).abs() < 0.3, "{}: {} then {}", integrator.name(), coarse, fine);
+        }
+        //with the same steps the fourth order one comes back to the start where the second order one misses by far
+        let (yoshida, _) = orbit_errors(Integrator::Yoshida4, 1000);
+        let (verlet, _) = orbit_errors(Integrator::VelocityVerlet, 1000);
+        assert!(yoshida < verlet * 1e-3, "{} m and {} m", yoshida, verlet);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn only_the_full_mode_lets_planets_pull_each_other() {
+        let (mass, distance) = (1.0e27, 1.0e11);
+        let bodies = vec![
+            PhysicsBody { is_star: true, ..PhysicsBody::point(0, 2.0e30, DVec3::ZERO, DVec3::ZERO) },
+            PhysicsBody { parent: Some(0), ..PhysicsBody::point(1, 1.0e24, DVec3::new(distance, 0.0, 0.0), DVec3::ZERO) },
+            PhysicsBody { parent: Some(0), ..PhysicsBody::point(2, mass, DVec3::new(distance, distance, 0.0), DVec3::ZERO) },
+        ];
+        let accelerations = |interaction_mode| {
+            let mut bodies = bodies.clone();
+            update_acceleration(&mut bodies, &GravitySettings { interaction_mode, ..GravitySettings::default() }, &mut 0);
+            bodies.iter().map(|body| body.acceleration).collect::<Vec<_>>()
+        };
+        let (hierarchical, full) = (accelerations(InteractionMode::Hierarchical), accelerations(InteractionMode::Full));
+        //the star pulls on both in either mode, the planets only see each other in the full one
+        assert_eq!(hierarchical[1].y, 0.0);
+        let expected = G * mass / (distance * distance);
+        assert!(((full[1] - hierarchical[1]).y - expected).abs() < expected * 1e-9);
+        assert!((full[1].x - hierarchical[1].x).abs() < expected * 1e-9);
+    }
+
+    #[test]
+    fn bodies_at_the_same_position_stay_finite() {
+        let body = |index: u32, mass: f64| PhysicsBody::point(index, mass, DVec3::new(1.0e9, 0.0, 0.0), DVec3::ZERO);
+        let far = PhysicsBody::point(2, 1.0e24, DVec3::new(-1.0e9, 0.0, 0.0), DVec3::ZERO);
+        for solver in Solver::ALL {
+            let gravity = GravitySettings { interaction_mode: InteractionMode::Full, solver, parallel: false, ..GravitySettings::default() };
+            let mut bodies = vec![body(0, 1.0e24), body(1, 1.0e22), far.clone()];
+            update_acceleration(&mut bodies, &gravity, &mut 0);
+            //the pair on top of each other only feels the third body
+            let expected = G * far.mass / 4.0e18;
+            for body in &bodies[..2] {
+                assert!(body.acceleration.is_finite(), "{}", solver.name());
+                assert!((body.acceleration.x + expected).abs() < expected * 1e-12, "{}", solver.name());
+            }
+        }
+        assert_eq!(barnes_hut::pull(DVec3::ONE, DVec3::ONE, 1.0e24, 0.0), DVec3::ZERO);
+    }
+
+    #[test]
+    fn parallel_forces_match_serial_forces() {
+        //a star, planets with moons and test particles, enough of both to take the parallel paths
+        let mut bodies: Vec<PhysicsBody> = (0..170u32).map(|index| {
+            let angle = index as f64 * 2.399963;
+            let distance = 1.0e10 * (1.0 + index as f64 * 0.37 % 11.0);
+            let (mass, parent) = match index {
+                0 => (2.0e30, None),
+                1..=40 => (1.0e24 * (1.0 + index as f64 % 7.0), Some(0)),
+                41..=99 => (1.0e20 * (1.0 + index as f64 % 5.0), Some((index % 40 + 1) as usize)),
+                _ => (0.0, Some((index % 40 + 1) as usize)),
+            };
+            let position = DVec3::new(angle.cos(), angle.sin(), (angle * 0.1).sin() * 0.05) * distance;
+            PhysicsBody { is_star: index == 0, parent, ..PhysicsBody::point(index, mass, position, DVec3::ZERO) }
+        }).collect();
+        bodies[0].position = DVec3::ZERO;
+        let settings = [
+            (InteractionMode::Hierarchical, Solver::Direct),
+            (InteractionMode::Full, Solver::Direct),
+            (InteractionMode::Full, Solver::BarnesHut),
+        ];
+        for (interaction_mode, solver) in settings {
+            let serial = GravitySettings { interaction_mode, solver, parallel: false, ..GravitySettings::default() };
+            let parallel = GravitySettings { parallel: true, ..serial };
+            let (mut first, mut second) = (bodies.clone(), bodies.clone());
+            update_acceleration(&mut first, &serial, &mut 0);
+            update_acceleration(&mut second, &parallel, &mut 0);
+            for (serial_body, parallel_body) in first.iter().zip(&second) {
+                assert_eq!(serial_body.acceleration, parallel_body.acceleration, "{} {}", interaction_mode.name(), solver.name());
+            }
+        }
+    }
+
+}
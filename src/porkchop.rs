@@ -0,0 +1,447 @@
+use bevy::app::{App, Plugin, Update};
+use bevy::core::Name;
+use bevy::math::{DQuat, DVec3};
+use bevy::prelude::{Entity, Has, in_state, IntoSystemConfigs, Query, Res, ResMut, Resource};
+use bevy::tasks::{AsyncComputeTaskPool, block_on, Task};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::body::{Acceleration, AxialTilt, BodyParent, Diameter, Mass, Oblateness, SimPosition, Star, Velocity};
+use crate::constants::G;
+use crate::kepler::lambert;
+use crate::physics::{GravitySettings, Integrator, integrate, link_parents, PhysicsBody, SimTime, update_acceleration};
+use crate::setup::StartingTime;
+use crate::SimState;
+use crate::transfer::{departure_lead, Launch, Trajectory, TransferPlanner};
+use crate::ui::{system_ui, UiState};
+
+/// Substeps the ephemerides of the origin and the target are integrated with
+const MAX_STEPS: usize = 50_000;
+/// Shorter flights are left out of the plot
+const MIN_TIME_OF_FLIGHT: f64 = 86400.0;
+/// Number of contour lines between the best value and the upper limit
+const CONTOURS: f64 = 10.0;
+const PLOT_SIZE: egui::Vec2 = egui::vec2(420.0, 320.0);
+
+pub struct PorkchopPlugin;
+
+impl Plugin for PorkchopPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<PorkchopSettings>()
+            .init_resource::<PorkchopTask>()
+            .add_systems(Update, (start_porkchop, finish_porkchop.after(start_porkchop), porkchop_window.after(system_ui)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PorkchopValue {
+    /// Characteristic energy of the departure, the square of the excess speed the launch has to provide
+    #[default]
+    C3,
+    /// Excess speed at the departure and at the arrival, like the burns of the transfer planner
+    DeltaV,
+}
+
+impl PorkchopValue {
+
+    pub const ALL: [PorkchopValue; 2] = [PorkchopValue::C3, PorkchopValue::DeltaV];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PorkchopValue::C3 => "C3",
+            PorkchopValue::DeltaV => "Total Δv",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            PorkchopValue::C3 => "km²/s²",
+            PorkchopValue::DeltaV => "km/s",
+        }
+    }
+
+    /// Upper limit of the color scale after switching to this value
+    pub fn default_limit(&self) -> f64 {
+        match self {
+            PorkchopValue::C3 => 50.0,
+            PorkchopValue::DeltaV => 15.0,
+        }
+    }
+
+}
+
+#[derive(Resource)]
+pub struct PorkchopSettings {
+    pub origin: Option<Entity>,
+    pub target: Option<Entity>,
+    /// Departures from now on, in days
+    pub departure_span: f64,
+    /// Arrivals from `arrival_start` days from now on, in days
+    pub arrival_start: f64,
+    pub arrival_span: f64,
+    /// Cells along each axis
+    pub resolution: usize,
+    pub value: PorkchopValue,
+    /// Cells above this are left out
+    pub limit: f64,
+    /// Set by the ui to start a new computation
+    pub requested: bool,
+}
+
+impl Default for PorkchopSettings {
+
+    fn default() -> Self {
+        PorkchopSettings {
+            origin: None,
+            target: None,
+            departure_span: 800.0,
+            arrival_start: 100.0,
+            arrival_span: 1200.0,
+            resolution: 60,
+            value: PorkchopValue::C3,
+            limit: PorkchopValue::C3.default_limit(),
+            requested: false,
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PorkchopCell {
+    /// In m²/s²
+    pub c3: f64,
+    /// Speed relative to the target at the arrival in m/s
+    pub arrival_speed: f64,
+    /// Velocity right after the departure relative to the parent
+    pub velocity: DVec3,
+}
+
+impl PorkchopCell {
+
+    pub fn value(&self, value: PorkchopValue) -> f64 {
+        match value {
+            PorkchopValue::C3 => self.c3 / 1e6,
+            PorkchopValue::DeltaV => (self.c3.sqrt() + self.arrival_speed) / 1000.0,
+        }
+    }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Porkchop {
+    pub origin: Entity,
+    pub target: Entity,
+    /// Simulated times of the columns and rows
+    pub departures: Vec<f64>,
+    pub arrivals: Vec<f64>,
+    /// Row by row, starting with the earliest arrival
+    pub cells: Vec<Option<PorkchopCell>>,
+    /// Angle the spacecraft starts ahead of the origin
+    pub lead: f64,
+}
+
+impl Porkchop {
+
+    fn cell(&self, column: usize, row: usize) -> Option<&PorkchopCell> {
+        self.cells[row * self.departures.len() + column].as_ref()
+    }
+
+}
+
+#[derive(Resource, Default)]
+pub struct PorkchopTask {
+    task: Option<Task<Porkchop>>,
+    pub result: Option<Porkchop>,
+    /// Why the last request couldn't be computed
+    pub error: Option<String>,
+}
+
+/// Positions and velocities relative to the parent at every substep, for the departure and arrival dates in between
+struct Ephemeris {
+    start: f64,
+    step: f64,
+    states: Vec<(DVec3, DVec3)>,
+}
+
+impl Ephemeris {
+
+    /// Cubic Hermite interpolation between the two closest substeps
+    fn state(&self, time: f64) -> (DVec3, DVec3) {
+        let position = ((time - self.start) / self.step).max(0.0);
+        let index = (position as usize).min(self.states.len() - 2);
+        let s = (position - index as f64).min(1.0);
+        let ((p0, v0), (p1, v1)) = (self.states[index], self.states[index + 1]);
+        let (v0, v1) = (v0 * self.step, v1 * self.step);
+        let (s2, s3) = (s * s, s * s * s);
+        let position = p0 * (2.0 * s3 - 3.0 * s2 + 1.0) + v0 * (s3 - 2.0 * s2 + s) + p1 * (-2.0 * s3 + 3.0 * s2) + v1 * (s3 - s2);
+        let velocity = (p0 * (6.0 * s2 - 6.0 * s) + v0 * (3.0 * s2 - 4.0 * s + 1.0) + p1 * (-6.0 * s2 + 6.0 * s) + v1 * (3.0 * s2 - 2.0 * s)) / self.step;
+        (position, velocity)
+    }
+
+}
+
+/// Copies the stars, the parent and its children and starts the computation in the background
+fn start_porkchop(
+    mut task: ResMut<PorkchopTask>,
+    mut settings: ResMut<PorkchopSettings>,
+    bodies: Query<(Entity, &Mass, &Diameter, &AxialTilt, Option<&Oblateness>, &Acceleration, &Velocity, &SimPosition, Has<Star>, Option<&BodyParent>)>,
+    gravity: Res<GravitySettings>,
+    sim_time: Res<SimTime>,
+) {
+    if !settings.requested || task.task.is_some() {
+        return;
+    }
+    settings.requested = false;
+    task.error = None;
+    let (Some(origin), Some(target)) = (settings.origin, settings.target) else {
+        return;
+    };
+    let parent_of = |entity: Entity| bodies.get(entity).ok().and_then(|(.., parent)| parent.map(|parent| parent.0));
+    let Some(parent) = parent_of(origin).filter(|parent| parent_of(target) == Some(*parent) && origin != target) else {
+        return;
+    };
+    //the moons barely move their planets, leaving them out allows much larger steps
+    let bodies = link_parents(bodies.iter().filter(|(entity, .., is_star, body_parent)| {
+        *is_star || *entity == parent || body_parent.is_some_and(|body_parent| body_parent.0 == parent)
+    }).map(|(entity, mass, diameter, tilt, oblateness, acc, vel, pos, is_star, parent)| {
+        (PhysicsBody::new(entity, mass, diameter, tilt, oblateness, acc, vel, pos, is_star), parent)
+    }).collect());
+    let index = |entity: Entity| bodies.iter().position(|body| body.entity == entity);
+    let (Some(parent_index), Some(origin_index), Some(target_index)) = (index(parent), index(origin), index(target)) else {
+        //a barycenter isn't simulated, so there is nothing to integrate the orbits around
+        task.error = Some("The parent has to be a simulated body, orbits around a barycenter aren't supported".to_string());
+        return;
+    };
+    let gm = G * bodies[parent_index].mass;
+    let origin_state = &bodies[origin_index];
+    let lead = departure_lead(origin_state.mass, bodies[parent_index].mass, origin_state.position.distance(bodies[parent_index].position));
+    let resolution = settings.resolution.max(2);
+    let axis = |start: f64, span: f64| (0..resolution).map(|i| sim_time.0 + (start + span * i as f64 / (resolution - 1) as f64) * 86400.0).collect::<Vec<_>>();
+    let departures = axis(0.0, settings.departure_span);
+    let arrivals = axis(settings.arrival_start, settings.arrival_span);
+    let gravity = *gravity;
+    let now = sim_time.0;
+    task.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+        let end = departures.last().unwrap().max(*arrivals.last().unwrap());
+        let [origin_ephemeris, target_ephemeris] = ephemerides(bodies, parent_index, [origin_index, target_index], &gravity, now, end);
+        let cells = arrivals.iter().flat_map(|arrival| departures.iter().map(move |departure| (*departure, *arrival))).map(|(departure, arrival)| {
+            if arrival - departure < MIN_TIME_OF_FLIGHT {
+                return None;
+            }
+            let (position, velocity) = origin_ephemeris.state(departure);
+            let normal = position.cross(velocity).normalize();
+            let rotation = DQuat::from_axis_angle(normal, lead);
+            let (target_position, target_velocity) = target_ephemeris.state(arrival);
+            let (departure_velocity, arrival_velocity) = lambert(rotation * position, target_position, arrival - departure, gm, normal)?;
+            Some(PorkchopCell {
+                c3: (departure_velocity - rotation * velocity).length_squared(),
+                arrival_speed: (arrival_velocity - target_velocity).length(),
+                velocity: departure_velocity,
+            })
+        }).collect();
+        Porkchop { origin, target, departures, arrivals, cells, lead }
+    }));
+}
+
+/// Integrates the copied system until `end` and records the states of the bodies at `indices` relative to the parent
+fn ephemerides<const N: usize>(
+    mut bodies: Vec<PhysicsBody>,
+    parent: usize,
+    indices: [usize; N],
+    gravity: &GravitySettings,
+    start: f64,
+    end: f64,
+) -> [Ephemeris; N] {
+    let step = (end - start).max(1.0) / MAX_STEPS as f64;
+    let mut ephemerides = indices.map(|_| Ephemeris { start, step, states: Vec::with_capacity(MAX_STEPS + 1) });
+    let mut steps = 0;
+    update_acceleration(&mut bodies, gravity, &mut steps);
+    for substep in 0..=MAX_STEPS {
+        for (ephemeris, index) in ephemerides.iter_mut().zip(indices) {
+            ephemeris.states.push((bodies[index].position - bodies[parent].position, bodies[index].velocity - bodies[parent].velocity));
+        }
+        if substep < MAX_STEPS {
+            integrate(&mut bodies, Integrator::Yoshida4, gravity, step, &mut steps);
+        }
+    }
+    ephemerides
+}
+
+fn finish_porkchop(
+    mut task: ResMut<PorkchopTask>,
+) {
+    if !task.task.as_ref().is_some_and(|task| task.is_finished()) {
+        return;
+    }
+    let result = block_on(task.task.take().unwrap());
+    task.result = Some(result);
+}
+
+/// Blue for the best values up to red at the limit
+fn cell_color(value: f64, best: f64, limit: f64) -> egui::Color32 {
+    let fraction = ((value - best) / (limit - best).max(1e-9)).clamp(0.0, 1.0) as f32;
+    egui::ecolor::Hsva::new(0.66 * (1.0 - fraction), 0.8, 0.9, 1.0).into()
+}
+
+fn porkchop_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut settings: ResMut<PorkchopSettings>,
+    task: Res<PorkchopTask>,
+    mut planner: ResMut<TransferPlanner>,
+    bodies: Query<(Entity, &Name, Option<&BodyParent>)>,
+    sim_time: Res<SimTime>,
+    starting_time: Res<StartingTime>,
+) {
+    if !ui_state.visible {
+        return;
+    }
+    let date = |time: f64| SimTime(time).date(&starting_time).format("%d.%m.%Y").to_string();
+    let mut names = bodies.iter().filter(|(.., parent)| parent.is_some()).map(|(entity, name, _)| (entity, name.to_string())).collect::<Vec<_>>();
+    names.sort_by(|(_, first), (_, second)| first.cmp(second));
+    let name_of = |entity: Option<Entity>| entity.and_then(|entity| names.iter().find(|(other, _)| *other == entity)).map(|(_, name)| name.clone()).unwrap_or_else(|| "None".to_string());
+    let parent_of = |entity: Option<Entity>| entity.and_then(|entity| bodies.get(entity).ok()).and_then(|(.., parent)| parent.map(|parent| parent.0));
+    egui::Window::new("Porkchop Plot")
+        .open(&mut ui_state.show_porkchop)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            let (origin, origin_parent) = (settings.origin, parent_of(settings.origin));
+            ui.horizontal(|ui| {
+                ui.label("From");
+                egui::ComboBox::from_id_source("porkchop_origin")
+                    .selected_text(name_of(settings.origin))
+                    .show_ui(ui, |ui| {
+                        for (entity, name) in &names {
+                            ui.selectable_value(&mut settings.origin, Some(*entity), name);
+                        }
+                    });
+                ui.label("To");
+                egui::ComboBox::from_id_source("porkchop_target")
+                    .selected_text(name_of(settings.target))
+                    .show_ui(ui, |ui| {
+                        for (entity, name) in names.iter().filter(|(entity, _)| parent_of(Some(*entity)) == origin_parent && Some(*entity) != origin) {
+                            ui.selectable_value(&mut settings.target, Some(*entity), name);
+                        }
+                    });
+            });
+            egui::Grid::new("porkchop_settings").show(ui, |ui| {
+                ui.label("Departure within");
+                ui.add(egui::DragValue::new(&mut settings.departure_span).speed(10.0).clamp_range(1.0..=20000.0).suffix(" days"));
+                ui.end_row();
+                ui.label("Arrival from");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut settings.arrival_start).speed(10.0).clamp_range(1.0..=20000.0).suffix(" days"));
+                    ui.label("within");
+                    ui.add(egui::DragValue::new(&mut settings.arrival_span).speed(10.0).clamp_range(1.0..=20000.0).suffix(" days"));
+                });
+                ui.end_row();
+                ui.label("Resolution");
+                ui.add(egui::Slider::new(&mut settings.resolution, 10..=150));
+                ui.end_row();
+            });
+            let computing = task.task.is_some();
+            let ready = settings.target.is_some() && parent_of(settings.target) == origin_parent && settings.target != origin;
+            ui.horizontal(|ui| {
+                if ui.add_enabled(ready && !computing, egui::Button::new("Compute")).clicked() {
+                    settings.requested = true;
+                }
+                if computing {
+                    ui.spinner();
+                    ui.label("Integrating and solving...");
+                }
+            });
+            if let Some(error) = &task.error {
+                ui.label(error);
+            }
+            let Some(porkchop) = &task.result else {
+                return;
+            };
+            ui.separator();
+            ui.horizontal(|ui| {
+                for option in PorkchopValue::ALL {
+                    if ui.selectable_value(&mut settings.value, option, option.name()).changed() {
+                        settings.limit = option.default_limit();
+                    }
+                }
+                ui.label("up to");
+                let unit = settings.value.unit();
+                ui.add(egui::DragValue::new(&mut settings.limit).speed(0.5).clamp_range(0.1..=10000.0).suffix(format!(" {}", unit)));
+            });
+            let value = settings.value;
+            let (columns, rows) = (porkchop.departures.len(), porkchop.arrivals.len());
+            let values = porkchop.cells.iter().map(|cell| cell.map(|cell| cell.value(value)).filter(|value| value.is_finite())).collect::<Vec<_>>();
+            let best = values.iter().enumerate().filter_map(|(index, value)| value.map(|value| (index, value))).min_by(|(_, first), (_, second)| first.total_cmp(second));
+            let Some((best_index, best_value)) = best else {
+                ui.label("No trajectory found");
+                return;
+            };
+            let limit = settings.limit.max(best_value);
+            let level = |value: Option<f64>| value.filter(|value| *value <= limit).map(|value| ((value - best_value) / (limit - best_value).max(1e-9) * CONTOURS).floor() as i32);
+
+            let (response, painter) = ui.allocate_painter(PLOT_SIZE, egui::Sense::click());
+            let rect = response.rect;
+            let cell_size = egui::vec2(rect.width() / columns as f32, rect.height() / rows as f32);
+            //the arrivals go up
+            let cell_rect = |column: usize, row: usize| egui::Rect::from_min_size(egui::pos2(rect.left() + column as f32 * cell_size.x, rect.bottom() - (row + 1) as f32 * cell_size.y), cell_size);
+            painter.rect_filled(rect, 0.0, egui::Color32::from_gray(30));
+            for row in 0..rows {
+                for column in 0..columns {
+                    let value = values[row * columns + column];
+                    if let Some(value) = value.filter(|value| *value <= limit) {
+                        painter.rect_filled(cell_rect(column, row), 0.0, cell_color(value, best_value, limit));
+                    }
+                    //contour lines where the level changes to the next cell
+                    let contour = egui::Stroke::new(1.0, egui::Color32::from_gray(20));
+                    let here = level(value);
+                    if column + 1 < columns && here != level(values[row * columns + column + 1]) {
+                        let edge = cell_rect(column, row);
+                        painter.line_segment([edge.right_top(), edge.right_bottom()], contour);
+                    }
+                    if row + 1 < rows && here != level(values[(row + 1) * columns + column]) {
+                        let edge = cell_rect(column, row);
+                        painter.line_segment([edge.left_top(), edge.right_top()], contour);
+                    }
+                }
+            }
+            painter.circle_stroke(cell_rect(best_index % columns, best_index / columns).center(), 4.0, egui::Stroke::new(2.0, egui::Color32::WHITE));
+            ui.label(format!("Departure (right) {} to {}", date(porkchop.departures[0]), date(*porkchop.departures.last().unwrap())));
+            ui.label(format!("Arrival (up) {} to {}", date(porkchop.arrivals[0]), date(*porkchop.arrivals.last().unwrap())));
+            let describe = |column: usize, row: usize| {
+                let (departure, arrival) = (porkchop.departures[column], porkchop.arrivals[row]);
+                let mut text = format!("Departure {}, Arrival {} ({:.0} days)", date(departure), date(arrival), (arrival - departure) / 86400.0);
+                if let Some(cell) = porkchop.cell(column, row) {
+                    text += &format!("\nC3 {:.2} km²/s², Total Δv {:.3} km/s", cell.value(PorkchopValue::C3), cell.value(PorkchopValue::DeltaV));
+                }
+                text
+            };
+            ui.label(format!("Best: {}", describe(best_index % columns, best_index / columns)));
+
+            let hovered = response.hover_pos().filter(|pos| rect.contains(*pos)).map(|pos| {
+                let column = (((pos.x - rect.left()) / cell_size.x) as usize).min(columns - 1);
+                let row = (((rect.bottom() - pos.y) / cell_size.y) as usize).min(rows - 1);
+                (column, row)
+            });
+            if let Some((column, row)) = hovered {
+                let response = response.clone().on_hover_text(describe(column, row));
+                if response.clicked() {
+                    let departure = porkchop.departures[column];
+                    match porkchop.cell(column, row) {
+                        Some(_) if departure < sim_time.0 => {}
+                        Some(cell) => planner.pending = Some(Launch {
+                            origin: porkchop.origin,
+                            target: porkchop.target,
+                            departure,
+                            arrival: porkchop.arrivals[row],
+                            trajectory: Trajectory::Lambert { velocity: cell.velocity, lead: porkchop.lead },
+                        }),
+                        None => {}
+                    }
+                }
+            }
+            match planner.pending.as_ref() {
+                Some(launch) => ui.label(format!("Spacecraft departs on {}, see the Transfer Planner", date(launch.departure))),
+                None => ui.label("Click a cell to spawn its trajectory, departures that have passed need a new plot"),
+            };
+        });
+}
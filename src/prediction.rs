@@ -0,0 +1,248 @@
+use bevy::app::{App, Plugin, Update};
+use bevy::math::DVec3;
+use bevy::prelude::{Added, Changed, Component, Entity, Gizmos, Has, in_state, IntoSystemConfigs, Query, Res, ResMut, Resource, With, Without};
+use bevy::tasks::{AsyncComputeTaskPool, block_on, Task};
+
+use crate::apsis::OsculatingElements;
+use crate::barycenter::Barycenter;
+use crate::body::{Acceleration, AxialTilt, BodyParent, Diameter, Mass, Oblateness, OrbitSettings, SimPosition, Star, Velocity};
+use crate::constants::M_TO_UNIT;
+use crate::orbit_lines::OrbitOffset;
+use crate::physics::{GravitySettings, Integrator, integrate, link_parents, PhysicsBody, SimTime, update_acceleration, update_translations};
+use crate::SimState;
+
+/// Most substeps a prediction may take, longer spans are cut short instead
+const MAX_STEPS: usize = 200_000;
+/// Substeps per revolution of the fastest orbit a prediction depends on
+const STEPS_PER_ORBIT: usize = 200;
+/// Number of points of every predicted path
+const SAMPLES: usize = 1000;
+/// Length of a prediction without a period to count, like an escape trajectory, in seconds
+const FALLBACK_SPAN: f64 = 365.0 * 86400.0;
+/// The prediction is redone once the simulation moved this fraction of its span past the start
+const REFRESH_FRACTION: f64 = 0.1;
+
+pub struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<PredictionSettings>()
+            .init_resource::<PredictionTask>()
+            .add_systems(Update, (start_prediction.after(update_translations), finish_prediction.after(start_prediction), draw_predictions.after(finish_prediction)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+/// Predicts the trajectory of a body and draws it as a dashed line, added with the checkbox in the body panel
+#[derive(Component, Default)]
+pub struct Prediction {
+    /// Relative to the parent at the same time, in meters
+    pub points: Vec<DVec3>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PredictionLength {
+    /// Revolutions of the predicted body around its parent
+    #[default]
+    Orbits,
+    Days,
+}
+
+impl PredictionLength {
+
+    pub const ALL: [PredictionLength; 2] = [PredictionLength::Orbits, PredictionLength::Days];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PredictionLength::Orbits => "Orbits",
+            PredictionLength::Days => "Days",
+        }
+    }
+
+}
+
+#[derive(Resource)]
+pub struct PredictionSettings {
+    pub length: PredictionLength,
+    pub amount: f64,
+    /// Set by the ui when the settings changed
+    pub dirty: bool,
+}
+
+impl Default for PredictionSettings {
+
+    fn default() -> Self {
+        PredictionSettings { length: PredictionLength::Orbits, amount: 1.0, dirty: false }
+    }
+
+}
+
+#[derive(Resource, Default)]
+pub struct PredictionTask {
+    task: Option<Task<Vec<(Entity, Vec<DVec3>)>>>,
+    /// A body was added or edited while the task was running, so its result is already outdated
+    pending: bool,
+    /// Simulated time and span of the latest prediction
+    start: f64,
+    span: f64,
+}
+
+/// What the predicted positions are relative to
+#[derive(Clone, Copy)]
+enum Frame {
+    Body(usize),
+    /// A parent that isn't simulated, like a barycenter, which moves on a straight line
+    Moving(DVec3, DVec3),
+}
+
+/// A predicted body, with the index of its copy in the integrated system
+struct Target {
+    entity: Entity,
+    index: usize,
+    frame: Frame,
+    steps_per_sample: usize,
+}
+
+/// Copies the system and integrates it in the background whenever something invalidates the current prediction
+fn start_prediction(
+    mut task: ResMut<PredictionTask>,
+    mut settings: ResMut<PredictionSettings>,
+    predicted: Query<(Entity, Option<&BodyParent>, Option<&OsculatingElements>), With<Prediction>>,
+    added: Query<(), Added<Prediction>>,
+    edited: Query<(), (Changed<Mass>, Without<Barycenter>)>,
+    bodies: Query<(Entity, &Mass, &Diameter, &AxialTilt, Option<&Oblateness>, &Acceleration, &Velocity, &SimPosition, Has<Star>, Option<&BodyParent>)>,
+    parents: Query<(&SimPosition, &Velocity, Option<&OsculatingElements>)>,
+    gravity: Res<GravitySettings>,
+    integrator: Res<Integrator>,
+    sim_time: Res<SimTime>,
+) {
+    //the changes are only seen once, so they are remembered until the running task is done
+    if !added.is_empty() || !edited.is_empty() {
+        task.pending = true;
+    }
+    if predicted.is_empty() || task.task.is_some() {
+        return;
+    }
+    let outdated = sim_time.0 - task.start > task.span * REFRESH_FRACTION;
+    if !settings.dirty && !task.pending && !outdated {
+        return;
+    }
+    settings.dirty = false;
+    task.pending = false;
+    let span_of = |elements: Option<&OsculatingElements>| match settings.length {
+        PredictionLength::Orbits => elements.map(|elements| elements.period).filter(|period| period.is_finite()).unwrap_or(FALLBACK_SPAN) * settings.amount,
+        PredictionLength::Days => settings.amount * 86400.0,
+    };
+    let max_span = predicted.iter().map(|(.., elements)| span_of(elements)).fold(0.0, f64::max);
+    if max_span <= 0.0 {
+        return;
+    }
+    //the step has to resolve the fastest orbit the paths depend on, the predicted bodies and their parents
+    let shortest_period = predicted.iter().flat_map(|(_, parent, elements)| {
+        let p_elements = parent.and_then(|parent| parents.get(parent.0).ok()).and_then(|(.., elements)| elements);
+        [elements, p_elements]
+    }).flatten().map(|elements| elements.period).filter(|period| period.is_finite()).fold(f64::INFINITY, f64::min);
+    let delta = (shortest_period / STEPS_PER_ORBIT as f64).min(max_span / SAMPLES as f64);
+    //rather a shorter path than steps that are too large for one of the orbits
+    let max_span = max_span.min(delta * MAX_STEPS as f64);
+    let bodies = link_parents(bodies.iter().map(|(entity, mass, diameter, tilt, oblateness, acc, vel, pos, is_star, parent)| {
+        (PhysicsBody::new(entity, mass, diameter, tilt, oblateness, acc, vel, pos, is_star), parent)
+    }).collect());
+    let targets = predicted.iter().filter_map(|(entity, parent, elements)| {
+        let index = bodies.iter().position(|body| body.entity == entity)?;
+        let frame = match parent.map(|parent| (parent.0, bodies.iter().position(|body| body.entity == parent.0))) {
+            Some((_, Some(p_index))) => Frame::Body(p_index),
+            Some((parent, None)) => parents.get(parent).map(|(pos, vel, _)| Frame::Moving(pos.0, vel.0)).unwrap_or(Frame::Moving(DVec3::ZERO, DVec3::ZERO)),
+            None => Frame::Moving(DVec3::ZERO, DVec3::ZERO),
+        };
+        let steps_per_sample = ((span_of(elements).min(max_span) / SAMPLES as f64 / delta).round() as usize).max(1);
+        Some(Target { entity, index, frame, steps_per_sample })
+    }).collect::<Vec<_>>();
+    //the adaptive integrator needs its own state, the fourth order one is accurate enough for a preview
+    let integrator = if integrator.is_adaptive() { Integrator::Yoshida4 } else { *integrator };
+    let gravity = *gravity;
+    task.start = sim_time.0;
+    task.span = max_span;
+    task.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+        predict(bodies, &targets, integrator, &gravity, delta)
+    }));
+}
+
+/// Integrates the copied system with a fixed step and samples every target at its own interval
+fn predict(
+    mut bodies: Vec<PhysicsBody>,
+    targets: &[Target],
+    integrator: Integrator,
+    gravity: &GravitySettings,
+    delta: f64,
+) -> Vec<(Entity, Vec<DVec3>)> {
+    let mut paths = targets.iter().map(|target| (target.entity, Vec::with_capacity(SAMPLES + 1))).collect::<Vec<_>>();
+    let mut diverged = vec![false; targets.len()];
+    let total = targets.iter().map(|target| target.steps_per_sample * SAMPLES).max().unwrap_or(0);
+    let mut steps = 0;
+    update_acceleration(&mut bodies, gravity, &mut steps);
+    for step in 0..=total {
+        let time = step as f64 * delta;
+        for ((target, (_, path)), diverged) in targets.iter().zip(paths.iter_mut()).zip(diverged.iter_mut()) {
+            if *diverged || path.len() > SAMPLES || step % target.steps_per_sample != 0 {
+                continue;
+            }
+            let origin = match target.frame {
+                Frame::Body(p_index) => bodies[p_index].position,
+                Frame::Moving(position, velocity) => position + velocity * time,
+            };
+            let point = bodies[target.index].position - origin;
+            //a close encounter that blows up only ends the path of the bodies involved
+            if point.is_finite() {
+                path.push(point);
+            } else {
+                *diverged = true;
+            }
+        }
+        if step == total || diverged.iter().all(|diverged| *diverged) {
+            break;
+        }
+        integrate(&mut bodies, integrator, gravity, delta, &mut steps);
+    }
+    paths
+}
+
+fn finish_prediction(
+    mut task: ResMut<PredictionTask>,
+    mut predicted: Query<&mut Prediction>,
+) {
+    if !task.task.as_ref().is_some_and(|task| task.is_finished()) {
+        return;
+    }
+    let paths = block_on(task.task.take().unwrap());
+    //bodies whose prediction was switched off in the meantime are skipped
+    for (entity, points) in paths {
+        if let Ok(mut prediction) = predicted.get_mut(entity) {
+            prediction.points = points;
+        }
+    }
+}
+
+fn draw_predictions(
+    bodies: Query<(&Prediction, &OrbitSettings, Option<&BodyParent>)>,
+    positions: Query<&SimPosition>,
+    offset: Res<OrbitOffset>,
+    mut gizmos: Gizmos,
+) {
+    for (prediction, orbit, parent) in &bodies {
+        //the points are relative to the parent, like the orbit lines
+        let p_pos = match parent {
+            Some(parent) => match positions.get(parent.0) {
+                Ok(p_pos) => p_pos.0,
+                Err(_) => continue,
+            },
+            None => DVec3::ZERO,
+        };
+        let points = prediction.points.iter().map(|point| ((p_pos + *point) * M_TO_UNIT).as_vec3() + offset.value).collect::<Vec<_>>();
+        for dash in points.chunks_exact(2) {
+            gizmos.line(dash[0], dash[1], orbit.color);
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use bevy::prelude::{App, Camera, Commands, DespawnRecursiveExt, Entity, NextState, OnEnter, OnExit, Plugin, Query, ResMut, Vec3, With, Without};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::{App, Camera, Commands, DespawnRecursiveExt, Entity, NextState, OnEnter, OnExit, Or, Plugin, Query, ResMut, Vec3, With, Without};
 
-use crate::{body::Mass, camera::{DEFAULT_CAM_RADIUS, PanOrbitCamera}, constants::{DEFAULT_SUB_STEPS, DEFAULT_TIMESTEP}, loading::LoadingState, physics::{Pause, SubSteps}, selection::SelectedEntity, setup::BodiesHandle, SimState, speed::Speed, ui::{SimTime, StepType, UiState}};
+use crate::{adaptive::AdaptiveStep, collision::CollisionLog, watchdog::Watchdog, body::Mass, camera::{DEFAULT_CAM_RADIUS, PanOrbitCamera}, constants::{DEFAULT_SUB_STEPS, DEFAULT_TIMESTEP}, loading::LoadingState, particles::{ParticleCloud, TestParticle}, physics::{Pause, SimTime, StepClock, SubSteps}, selection::SelectedEntity, setup::BodiesHandle, SimState, soi::SoiLog, prediction::PredictionTask, eclipse::EclipseLog, sky_events::{SkyEventLog, SkyEventSettings}, transfer::TransferPlanner, porkchop::{PorkchopSettings, PorkchopTask}, speed::Speed, ui::{StepType, UiState}};
 
 pub struct ResetPlugin;
 
@@ -15,27 +16,66 @@ impl Plugin for ResetPlugin {
     
 }
 
+/// Logs, tools and background tasks that belong to the current scenario
+#[derive(SystemParam)]
+struct ScenarioState<'w> {
+    collision_log: ResMut<'w, CollisionLog>,
+    watchdog: ResMut<'w, Watchdog>,
+    soi_log: ResMut<'w, SoiLog>,
+    prediction_task: ResMut<'w, PredictionTask>,
+    eclipse_log: ResMut<'w, EclipseLog>,
+    sky_events: ResMut<'w, SkyEventLog>,
+    sky_event_settings: ResMut<'w, SkyEventSettings>,
+    transfer_planner: ResMut<'w, TransferPlanner>,
+    porkchop_settings: ResMut<'w, PorkchopSettings>,
+    porkchop_task: ResMut<'w, PorkchopTask>,
+}
+
+impl ScenarioState<'_> {
+
+    fn clear(&mut self) {
+        self.collision_log.entries.clear();
+        self.collision_log.touching.clear();
+        self.watchdog.failure = None;
+        self.soi_log.entries.clear();
+        self.eclipse_log.clear();
+        self.sky_events.clear();
+        *self.sky_event_settings = SkyEventSettings::default(); //the chosen bodies are gone
+        *self.transfer_planner = TransferPlanner::default();
+        *self.porkchop_settings = PorkchopSettings::default();
+        *self.porkchop_task = PorkchopTask::default();
+        *self.prediction_task = PredictionTask::default(); //dropping a running prediction cancels it
+    }
+
+}
+
 fn clean_up(
-    m_entities: Query<(Entity, With<Mass>, Without<Camera>)>,
+    m_entities: Query<Entity, (Or<(With<Mass>, With<TestParticle>, With<ParticleCloud>)>, Without<Camera>)>,
     mut speed: ResMut<Speed>,
     mut pause: ResMut<Pause>,
     mut sim_time: ResMut<SimTime>,
+    mut clock: ResMut<StepClock>,
     mut selected_entity: ResMut<SelectedEntity>,
     mut bodies: ResMut<BodiesHandle>,
     mut sub_steps: ResMut<SubSteps>,
+    mut adaptive: ResMut<AdaptiveStep>,
+    mut scenario: ScenarioState,
     mut loading_state: ResMut<LoadingState>,
     mut commands: Commands,
     mut camera: Query<&mut PanOrbitCamera>,
     mut ui_state: ResMut<UiState>
 ) {
-    for (entity, _, _) in m_entities.iter() {
+    for entity in m_entities.iter() {
         commands.entity(entity).despawn_recursive()
     }
     speed.0 = DEFAULT_TIMESTEP;
     pause.0 = false;
     sim_time.0 = 0.0;
+    *clock = StepClock::default();
     selected_entity.entity = None;
     sub_steps.0 = DEFAULT_SUB_STEPS;
+    adaptive.step_size = DEFAULT_TIMESTEP;
+    scenario.clear();
     bodies.spawned = false;
     loading_state.reset();
     let mut cam = camera.single_mut();
@@ -44,6 +84,12 @@ fn clean_up(
     ui_state.visible = true;
     ui_state.step_type = StepType::SUBSTEPS;
     ui_state.show_debug = false;
+    ui_state.show_collisions = false;
+    ui_state.show_captures = false;
+    ui_state.show_eclipses = false;
+    ui_state.show_sky_events = false;
+    ui_state.show_transfers = false;
+    ui_state.show_porkchop = false;
 }
 
 fn switch_to_menu(
@@ -8,21 +8,49 @@ use bevy::asset::io::Reader;
 use bevy::prelude::{Asset, AssetApp};
 use serde::Deserialize;
 
+use crate::body::BodyKind;
+use crate::kepler::OrbitalElements;
+use crate::physics::InteractionMode;
+
 #[derive(Debug, Deserialize, TypeUuid, TypePath, Asset, Clone)]
 #[uuid = "39cadc56-aa9c-4543-8640-a018b74b5052"]
 pub struct SimulationData {
     pub bodies: Vec<SerializedBody>,
-    pub starting_time_millis: i64
+    #[serde(default)]
+    pub circumbinary: Vec<SerializedBody>, //orbit the barycenter of all stars
+    pub starting_time_millis: i64,
+    #[serde(default)]
+    pub interaction_mode: InteractionMode,
+    #[serde(default)]
+    pub softening_length: f64, //in km like the positions
+    #[serde(default)]
+    pub relativistic_correction: bool,
 }
 
 #[derive(Debug, Deserialize, TypeUuid, TypePath, Clone)]
 #[uuid = "39cadc56-aa9c-4543-8640-a018b74b5052"]
 pub struct SerializedBody {
     pub children: Vec<SerializedBody>,
-    pub data: SerializedBodyData
+    pub data: SerializedBodyData,
+    #[serde(default)]
+    pub particles: Vec<SerializedParticles>,
+}
+
+/// A ring or belt of massless test particles on random orbits around a body
+#[derive(Debug, Deserialize, Clone)]
+pub struct SerializedParticles {
+    pub count: usize,
+    pub inner_radius: f64, //in km
+    pub outer_radius: f64, //in km
+    #[serde(default)]
+    pub max_eccentricity: f64,
+    #[serde(default)]
+    pub max_inclination: f64, //in degrees
+    #[serde(default)]
+    pub equatorial: bool, //around the body's equator instead of the ecliptic, for planetary rings
 }
 
-#[derive(Debug, Deserialize, TypeUuid, TypePath, Clone, Copy)]
+#[derive(Debug, Deserialize, TypeUuid, TypePath, Clone, Copy, Default)]
 #[uuid = "39cadc56-aa9c-4543-8640-a018b74b5052"]
 pub struct SerializedVec {
     pub x: f64,
@@ -42,7 +70,9 @@ impl From<SerializedVec> for DVec3 {
 #[uuid = "39cadc56-aa9c-4543-8640-a018b74b5052"]
 pub struct SerializedBodyData {
     pub mass: f64,
+    #[serde(default)]
     pub starting_position: SerializedVec,
+    #[serde(default)]
     pub starting_velocity: SerializedVec,
     pub name: String,
     pub model_path: String,
@@ -50,6 +80,70 @@ pub struct SerializedBodyData {
     pub rotation_speed: f64,
     pub axial_tilt: f32,
     pub simulate: bool,
+    #[serde(default)]
+    pub j2: Option<f64>,
+    #[serde(default)]
+    pub equatorial_radius: Option<f64>, //in km, falls back to half of the diameter
+    #[serde(default)]
+    pub luminosity: Option<f64>, //in solar luminosities, only used for stars
+    #[serde(default, rename = "type")]
+    pub kind: Option<BodyKind>, //derived from the depth in the hierarchy if missing
+    #[serde(default)]
+    pub orbit: Option<SerializedOrbit>, //replaces the starting position and velocity
+    #[serde(default)]
+    pub maneuvers: Option<SerializedManeuvers>,
+}
+
+/// Burns a spacecraft performs during the simulation
+#[derive(Debug, Deserialize, Clone)]
+pub struct SerializedManeuvers {
+    pub burns: Vec<SerializedBurn>,
+    #[serde(default)]
+    pub delta_v_budget: Option<f64>, //in km/s, falls back to the sum of the burns
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct SerializedBurn {
+    pub time: f64, //in days after the starting time
+    #[serde(default)]
+    pub prograde: f64, //in km/s relative to the parent
+    #[serde(default)]
+    pub normal: f64, //in km/s, along the angular momentum
+    #[serde(default)]
+    pub radial: f64, //in km/s, away from the parent
+    #[serde(default)]
+    pub duration: f64, //in seconds, 0 for an impulsive burn
+}
+
+/// Keplerian elements at the starting time relative to the parent, in the ecliptic like the vectors from Horizons
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct SerializedOrbit {
+    pub semi_major_axis: f64, //in km
+    #[serde(default)]
+    pub eccentricity: f64, //above 1 for hyperbolic orbits
+    #[serde(default)]
+    pub inclination: f64, //in degrees
+    #[serde(default)]
+    pub ascending_node: f64, //in degrees
+    #[serde(default)]
+    pub argument_of_periapsis: f64, //in degrees
+    #[serde(default)]
+    pub mean_anomaly: f64, //in degrees
+}
+
+impl From<SerializedOrbit> for OrbitalElements {
+
+    fn from(value: SerializedOrbit) -> Self {
+        OrbitalElements {
+            semi_major_axis: value.semi_major_axis * 1000.0,
+            eccentricity: value.eccentricity,
+            inclination: value.inclination.to_radians(),
+            ascending_node: value.ascending_node.to_radians(),
+            argument_of_periapsis: value.argument_of_periapsis.to_radians(),
+            mean_anomaly: value.mean_anomaly.to_radians(),
+        }
+    }
+
 }
 
 #[derive(Default)]
@@ -7,18 +7,24 @@ use bevy::ecs::system::EntityCommands;
 use bevy::hierarchy::BuildChildren;
 use bevy::math::{DVec3, Vec3};
 use bevy::pbr::{PbrBundle, PointLight, PointLightBundle};
-use bevy::prelude::{Assets, Bundle, Camera, Camera3dBundle, ChildBuilder, Color, Commands, default, Entity, Handle, in_state, IntoSystemConfigs, Mesh, OnEnter, PerspectiveProjection, Projection, Res, ResMut, Resource, SceneBundle, shape, SpatialBundle, StandardMaterial, Startup, Transform, Update, Visibility};
+use bevy::prelude::{Assets, Bundle, Camera, Camera3dBundle, ChildBuilder, Color, Commands, default, Entity, Handle, in_state, IntoSystemConfigs, Mesh, OnEnter, PerspectiveProjection, Projection, Res, ResMut, Resource, SceneBundle, shape, SpatialBundle, StandardMaterial, Startup, Transform, Update, Visibility, World};
 use bevy::scene::Scene;
 use bevy::text::{TextAlignment, TextSection, TextStyle};
 use bevy_mod_billboard::{BillboardLockAxisBundle, BillboardTextBundle};
 
-use crate::apsis::ApsisBody;
-use crate::body::{BodyBundle, BodyChildren, BodyParent, Moon, OrbitSettings, Planet, SceneHandle, Star};
+use crate::apsis::{ApsisBody, OsculatingElements};
+use crate::barycenter::{Barycenter, center_of_mass, orbit_gm};
+use crate::billboard::{label_font_size, label_marker};
+use crate::body::{BodyBundle, BodyChildren, BodyKind, BodyParent, Mass, Oblateness, OrbitSettings, SceneHandle, SimPosition, Star, Velocity};
 use crate::camera::PanOrbitCamera;
 use crate::constants::M_TO_UNIT;
+use crate::kepler::OrbitalElements;
 use crate::loading::LoadingState;
+use crate::maneuver::ManeuverSchedule;
+use crate::particles::spawn_particles;
+use crate::physics::GravitySettings;
 use crate::selection::SelectedEntity;
-use crate::serialization::{SerializedBody, SerializedVec, SimulationData};
+use crate::serialization::{SerializedBody, SerializedBodyData, SerializedVec, SimulationData};
 use crate::SimState;
 use crate::skybox::Cubemap;
 use crate::star_renderer::StarBillboard;
@@ -47,6 +53,9 @@ pub struct BodiesHandle {
 #[derive(Resource, Default)]
 pub struct StartingTime(pub i64);
 
+/// Intensity of the point light of a star as bright as the sun
+const SUN_INTENSITY: f32 = 15000000000.0;
+
 pub fn load_bodies(
     assets: Res<AssetServer>,
     mut bodies_handle: ResMut<BodiesHandle>
@@ -61,6 +70,7 @@ pub fn setup_planets(
     mut bodies_handle: ResMut<BodiesHandle>,
     bodies_asset: ResMut<Assets<SimulationData>>,
     mut starting_time: ResMut<StartingTime>,
+    mut gravity: ResMut<GravitySettings>,
     mut loading_state: ResMut<LoadingState>,
     mut selected_entity: ResMut<SelectedEntity>,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -74,28 +84,54 @@ pub fn setup_planets(
     if bodies.cloned().is_none() {
         return;
     }
-    let data = bodies.unwrap();
+    let mut data = bodies.unwrap().clone();
+    resolve_root_orbits(&mut data);
     starting_time.0 = data.starting_time_millis;
-    let stars = data.bodies.iter().count();  
-    total_count += stars;
-    
-    //iterate through the stars
-    for (s_index, entry) in data.bodies.iter().enumerate() {
-        if !entry.data.simulate {
-            continue;
-        }
-        let mut star = commands.spawn(SpatialBundle::default());
-        let star_id = star.id();
-        if selected_entity.entity.is_none() {
-            selected_entity.change_entity(star_id);
-        }
-        
-        //planets vector for adding BodyChildren later
-        let mut planets: Vec<Entity> = vec![];
-        star.insert(PointLightBundle {
+    gravity.interaction_mode = data.interaction_mode;
+    gravity.softening = data.softening_length * 1000.0;
+    gravity.relativity = data.relativistic_correction;
+    let roots = data.bodies.iter().filter(|body| body.data.simulate).map(|body| (body, BodyKind::Star));
+    let circumbinary = data.circumbinary.iter().filter(|body| body.data.simulate).map(|body| (body, BodyKind::Planet));
+    let entries = roots.chain(circumbinary).collect::<Vec<_>>();
+    let root = match entries.as_slice() {
+        [] => None,
+        [(entry, kind)] => Some(spawn_body(entry, None, *kind, 0.0, &mut commands, &assets, &mut meshes, &mut materials, &mut total_count)),
+        //several stars orbit their common barycenter, which is also the parent of the circumbinary (P-type) planets
+        _ => Some(spawn_barycenter("Barycenter", &entries, None, &mut commands, &assets, &mut meshes, &mut materials, &mut total_count)),
+    };
+    if let Some(root) = root.filter(|_| selected_entity.entity.is_none()) {
+        selected_entity.change_entity(root); //a barycenter can't be selected, the selection then falls back to the first star
+    }
+    bodies_handle.spawned = true;
+    loading_state.loaded_bodies = true;
+    loading_state.total_bodies = total_count as i32;
+}
+
+/// Spawns a body and all of its simulated children at any depth. The top level bodies of a .sim file are the stars.
+fn spawn_body(
+    entry: &SerializedBody,
+    parent: Option<Entity>,
+    default_kind: BodyKind,
+    hue: f32,
+    commands: &mut Commands,
+    assets: &Res<AssetServer>,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    total_count: &mut usize,
+) -> Entity {
+    let kind = entry.data.kind.unwrap_or(default_kind);
+    if kind == BodyKind::Barycenter {
+        //the children of a barycenter are what they would be without it
+        let children = entry.children.iter().filter(|child| child.data.simulate).map(|child| (child, default_kind)).collect::<Vec<_>>();
+        return spawn_barycenter(&entry.data.name, &children, parent, commands, assets, meshes, materials, total_count);
+    }
+    let mut body = commands.spawn(SpatialBundle::default());
+    let id = body.id();
+    if kind == BodyKind::Star {
+        body.insert(PointLightBundle {
             point_light: PointLight {
                 color: Color::rgba(1.0, 1.0, 1.0, 1.0),
-                intensity: 15000000000.0,
+                intensity: SUN_INTENSITY * entry.data.luminosity.unwrap_or(1.0) as f32,
                 shadows_enabled: false,
                 range: 300000000000.0,
                 radius: 100.0,
@@ -103,67 +139,160 @@ pub fn setup_planets(
             },
             ..default()
         });
-        
-        //add the star's components
-        apply_body(BodyBundle::from(entry.clone()), Star::default(), &assets, &mut star, &mut meshes, &mut materials,360.0 * ((s_index + 1) as f32 / stars as f32), true);
-        
-        //planet count in star system for coloring later
-        let planet_count = entry.children.iter().filter(|p| p.data.simulate).count();
-        total_count += planet_count;
-        
-        //collect the planets in a new vector and sort them by the length of the position
-        let mut star_children = entry.children.iter().collect::<Vec<_>>();
-        sort_bodies(&mut star_children, DVec3::ZERO);
+        apply_body(BodyBundle::from(entry.clone()), Star::default(), kind, Oblateness::from_data(&entry.data), assets, &mut body, meshes, materials, hue);
+    } else {
+        apply_body(BodyBundle::from(entry.clone()), (), kind, Oblateness::from_data(&entry.data), assets, &mut body, meshes, materials, hue);
+    }
+    if let Some(parent) = parent {
+        body.insert((BodyParent(parent), ApsisBody::default(), OsculatingElements::default()));
+    }
+    if let Some(schedule) = ManeuverSchedule::from_data(&entry.data) {
+        body.insert(schedule);
+    }
+    *total_count += 1;
+    for (index, particles) in entry.particles.iter().enumerate() {
+        spawn_particles(particles, id, &entry.data, (*total_count * 31 + index) as u64, commands);
+    }
 
-        //iterate through the planets
-        for (p_index, planet_entry) in star_children.iter().enumerate() {
-            if !planet_entry.data.simulate {
-                continue;
-            }
-            let mut planet = star.commands().spawn(SpatialBundle::default());
-            let planet_id = planet.id();
-            
-            //moon vector for adding BodyChildren later
-            let mut moons: Vec<Entity> = vec![];
-            
-            //dereferenced planet entry (rust wants this in a new variable for some reason)
-            let de_planet_entry = *planet_entry;
-            
-            //add the planet's components
-            apply_body(BodyBundle::from(de_planet_entry.clone()), Planet, &assets, &mut planet, &mut meshes, &mut materials,360.0 * ((p_index + 1) as f32 / planet_count as f32), false);
-            //for the tree-based ui later
-            planets.push(planet_id);
-            
-            //moon count for coloring later
-            let moon_count = planet_entry.children.iter().filter(|m| m.data.simulate).count();
-            total_count += moon_count;
-                
-            //collect the moons in a new vector and sort them by the distance to the parent
-            let mut planet_children = de_planet_entry.children.iter().collect::<Vec<_>>();
-            sort_bodies(&mut planet_children, -serialized_vec_to_vec(de_planet_entry.clone().data.starting_position));
-            
-            //iterate through the moons
-            for (m_index, moon_entry) in planet_entry.children.iter().enumerate() {
-                if !moon_entry.data.simulate {
-                    continue;
-                }
-                let mut moon = planet.commands().spawn(SpatialBundle::default());
+    //sort the children by their distance to this body, this also decides their colors
+    let mut children = entry.children.iter().filter(|child| child.data.simulate).collect::<Vec<_>>();
+    sort_bodies(&mut children, -serialized_vec_to_vec(entry.data.starting_position));
+    let child_count = children.len();
+    let child_ids = children.iter().enumerate().map(|(index, child)| {
+        spawn_body(child, Some(id), kind.default_child(), 360.0 * ((index + 1) as f32 / child_count as f32), commands, assets, meshes, materials, total_count)
+    }).collect();
+    //for the tree-based ui and the hierarchical gravity
+    commands.entity(id).insert(BodyChildren(child_ids));
+    id
+}
 
-                //for the tree-based ui later                
-                moons.push(moon.id());
-                
-                //add the moon's components
-                apply_body(BodyBundle::from(moon_entry.clone()), Moon, &assets, &mut moon, &mut meshes, &mut materials, 360.0 * ((m_index + 1) as f32 / moon_count as f32), false);
-                moon.insert(BodyParent(planet_id));
-            }
-            planet.insert(BodyParent(star_id));
-            planet.insert(BodyChildren(moons));
-        }  
-        star.insert(BodyChildren(planets));
+/// Spawns a barycenter at the center of mass of the stars and barycenters among the entries, all entries become its children
+fn spawn_barycenter(
+    name: &str,
+    entries: &[(&SerializedBody, BodyKind)],
+    parent: Option<Entity>,
+    commands: &mut Commands,
+    assets: &Res<AssetServer>,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    total_count: &mut usize,
+) -> Entity {
+    //it already needs the right state for the first frame, update_barycenters only runs after the first physics step
+    let (mass, position, velocity) = members_center_of_mass(entries);
+    let mut barycenter = commands.spawn((SpatialBundle::default(), Name::new(name.to_string()), Barycenter, BodyKind::Barycenter, Mass(mass), SimPosition(position), Velocity(velocity)));
+    if let Some(parent) = parent {
+        barycenter.insert(BodyParent(parent));
+    }
+    let id = barycenter.id();
+    let child_ids = entries.iter().enumerate().map(|(index, (entry, kind))| {
+        spawn_body(entry, Some(id), *kind, 360.0 * ((index + 1) as f32 / entries.len() as f32), commands, assets, meshes, materials, total_count)
+    }).collect();
+    commands.entity(id).insert(BodyChildren(child_ids));
+    id
+}
+
+/// Spawns a massless body while the simulation is running, like a test body at a Lagrange point.
+/// The position and velocity are in meters and m/s, the body is added to the children of `parent`.
+pub fn spawn_massless_body(
+    name: String,
+    kind: BodyKind,
+    position: DVec3,
+    velocity: DVec3,
+    parent: Entity,
+    commands: &mut Commands,
+    assets: &Res<AssetServer>,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+) -> Entity {
+    let (model_path, diameter) = match kind {
+        BodyKind::Spacecraft => ("iss.glb", 0.11),
+        _ => ("deimos.glb", 10.0),
+    };
+    let entry = SerializedBody {
+        children: vec![],
+        data: SerializedBodyData {
+            mass: 0.0,
+            starting_position: vec_to_serialized_vec(position / 1000.0),
+            starting_velocity: vec_to_serialized_vec(velocity / 1000.0),
+            name,
+            model_path: model_path.to_string(),
+            diameter,
+            rotation_speed: 0.0,
+            axial_tilt: 0.0,
+            simulate: true,
+            j2: None,
+            equatorial_radius: None,
+            luminosity: None,
+            kind: Some(kind),
+            orbit: None,
+            maneuvers: None,
+        },
+        particles: vec![],
+    };
+    let id = spawn_body(&entry, Some(parent), kind, 300.0, commands, assets, meshes, materials, &mut 0);
+    commands.add(move |world: &mut World| {
+        let Some(mut parent) = world.get_entity_mut(parent) else {
+            return;
+        };
+        match parent.get_mut::<BodyChildren>() {
+            Some(mut children) => children.0.push(id),
+            None => { parent.insert(BodyChildren(vec![id])); }
+        }
+    });
+    id
+}
+
+fn members_center_of_mass(entries: &[(&SerializedBody, BodyKind)]) -> (f64, DVec3, DVec3) {
+    center_of_mass(entries.iter().filter_map(|(entry, default_kind)| match entry.data.kind.unwrap_or(*default_kind) {
+        BodyKind::Star => {
+            let bundle = BodyBundle::from((*entry).clone());
+            Some((bundle.mass.0, bundle.sim_position.0, bundle.vel.0))
+        }
+        BodyKind::Barycenter => {
+            let children = entry.children.iter().filter(|child| child.data.simulate).map(|child| (child, *default_kind)).collect::<Vec<_>>();
+            Some(members_center_of_mass(&children))
+        }
+        _ => None,
+    }))
+}
+
+/// Bodies defined by orbital elements need their parent's state, so the whole tree is resolved before anything is spawned
+fn resolve_root_orbits(data: &mut SimulationData) {
+    let roots = data.bodies.iter().filter(|body| body.data.simulate).map(|body| (body, BodyKind::Star));
+    let circumbinary = data.circumbinary.iter().filter(|body| body.data.simulate).map(|body| (body, BodyKind::Planet));
+    let entries = roots.chain(circumbinary).collect::<Vec<_>>();
+    //a single root has nothing to orbit, several orbit the barycenter of the stars
+    let barycenter = (entries.len() > 1).then(|| members_center_of_mass(&entries));
+    resolve_orbits(&mut data.bodies, BodyKind::Star, barycenter, true);
+    resolve_orbits(&mut data.circumbinary, BodyKind::Planet, barycenter, true);
+}
+
+/// Replaces the orbital elements of the bodies by the starting position and velocity they describe,
+/// `parent` being the mass, position and velocity of the parent in SI units
+fn resolve_orbits(
+    bodies: &mut [SerializedBody],
+    default_kind: BodyKind,
+    parent: Option<(f64, DVec3, DVec3)>,
+    parent_is_barycenter: bool,
+) {
+    for body in bodies.iter_mut() {
+        let kind = body.data.kind.unwrap_or(default_kind);
+        if kind == BodyKind::Barycenter {
+            //the stars of a barycenter need vectors, its state comes from them
+            let members = body.children.iter().filter(|child| child.data.simulate).map(|child| (child, default_kind)).collect::<Vec<_>>();
+            let state = members_center_of_mass(&members);
+            resolve_orbits(&mut body.children, default_kind, Some(state), true);
+            continue;
+        }
+        if let (Some(orbit), Some((p_mass, p_position, p_velocity))) = (body.data.orbit, parent) {
+            let gm = orbit_gm(p_mass, body.data.mass, parent_is_barycenter && kind == BodyKind::Star);
+            let (position, velocity) = OrbitalElements::from(orbit).to_state(gm);
+            body.data.starting_position = vec_to_serialized_vec((p_position + position) / 1000.0);
+            body.data.starting_velocity = vec_to_serialized_vec((p_velocity + velocity) / 1000.0);
+        }
+        let state = (body.data.mass, DVec3::from(body.data.starting_position) * 1000.0, DVec3::from(body.data.starting_velocity) * 1000.0);
+        resolve_orbits(&mut body.children, kind.default_child(), Some(state), false);
     }
-    bodies_handle.spawned = true;
-    loading_state.loaded_bodies = true;
-    loading_state.total_bodies = total_count as i32;
 }
 
 fn sort_bodies(
@@ -183,27 +312,34 @@ fn serialized_vec_to_vec(
     DVec3::new(serialized_vec.x, serialized_vec.y, serialized_vec.z)
 }
 
+fn vec_to_serialized_vec(
+    vec: DVec3
+) -> SerializedVec {
+    SerializedVec { x: vec.x, y: vec.y, z: vec.z }
+}
+
 fn apply_body(
     bundle: BodyBundle,
     body_type: impl Bundle,
+    kind: BodyKind,
+    oblateness: Option<Oblateness>,
     assets: &Res<AssetServer>,
     entity: &mut EntityCommands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
     hue: f32,
-    is_star: bool,
 ) {
     let asset_handle: Handle<Scene> = assets.load(bundle.model_path.clone().0);
     let color = Color::hsl(hue, 1.0, 0.5);
     entity.insert(bundle.clone());
-    entity.insert(body_type);
+    entity.insert((body_type, kind));
+    if let Some(oblateness) = oblateness {
+        entity.insert(oblateness);
+    }
     entity.insert(OrbitSettings {
         color,
        ..default() 
     });
-    if !is_star {
-        entity.insert(ApsisBody::default());
-    }
     entity.insert(SceneHandle(asset_handle.clone()));
     entity.with_children(|parent| {
 
@@ -215,11 +351,12 @@ fn apply_body(
 
         spawn_billboard(
             bundle.clone(),
+            kind,
             color,
             parent
         );
         
-        if is_star {
+        if kind == BodyKind::Star {
             spawn_imposter(
                 bundle.clone(),
                 parent,
@@ -261,6 +398,7 @@ fn spawn_scene(
 
 fn spawn_billboard(
     bundle: BodyBundle,
+    kind: BodyKind,
     color: Color,
     parent: &mut ChildBuilder
 ) {
@@ -268,11 +406,20 @@ fn spawn_billboard(
         billboard_bundle: BillboardTextBundle {
             transform: Transform::from_translation(Vec3::new(0., 2000., 0.))
                 .with_scale(Vec3::splat(8.5)),
+            //the name goes last, a merge only replaces that section
             text: bevy::text::Text::from_sections([
+                TextSection {
+                    value: format!("{} ", label_marker(kind)),
+                    style: TextStyle {
+                        font_size: label_font_size(kind),
+                        color,
+                        ..default()
+                    }
+                },
                 TextSection {
                     value: bundle.name.to_string(),
                     style: TextStyle {
-                        font_size: 60.0,
+                        font_size: label_font_size(kind),
                         // font: fira_sans_regular_handle.clone(),
                         color,
                         ..default()
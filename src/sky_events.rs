@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::app::{App, FixedUpdate, Plugin, Update};
+use bevy::core::Name;
+use bevy::log::{error, info};
+use bevy::math::DVec3;
+use bevy::prelude::{Entity, in_state, IntoSystemConfigs, Local, Query, Res, ResMut, Resource, With};
+use bevy_egui::{egui, EguiContexts};
+use chrono::NaiveDateTime;
+
+use crate::body::{BodyKind, BodyParent, Mass, SimPosition};
+use crate::constants::M_TO_AU;
+use crate::eclipse::parabola_minimum;
+use crate::physics::{apply_physics, Pause, SimTime};
+use crate::setup::StartingTime;
+use crate::SimState;
+use crate::ui::{system_ui, UiState};
+use crate::unit::format_length;
+
+/// Length of the event log, twice the one of the eclipses since every planet has a conjunction and an opposition each synodic period
+const MAX_EVENTS: usize = 1000;
+/// File the events are exported to, next to the executable's working directory
+const EXPORT_PATH: &str = "sky_events.csv";
+
+pub struct SkyEventsPlugin;
+
+impl Plugin for SkyEventsPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<SkyEventSettings>()
+            .init_resource::<SkyEventLog>()
+            .add_systems(FixedUpdate, (detect_sky_events.after(apply_physics)).run_if(in_state(SimState::Simulation)))
+            .add_systems(Update, (sky_event_window.after(system_ui)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkyEventKind {
+    /// The body has the same ecliptic longitude as the star, seen from the observer
+    Conjunction,
+    /// The body is opposite of the star in ecliptic longitude, seen from the observer
+    Opposition,
+    /// Smallest distance between two chosen bodies
+    ClosestApproach,
+}
+
+impl SkyEventKind {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SkyEventKind::Conjunction => "Conjunction",
+            SkyEventKind::Opposition => "Opposition",
+            SkyEventKind::ClosestApproach => "Closest Approach",
+        }
+    }
+
+}
+
+#[derive(Resource, Default)]
+pub struct SkyEventSettings {
+    /// Body the conjunctions and oppositions are seen from, none turns them off
+    pub observer: Option<Entity>,
+    /// Pairs whose closest approaches are recorded
+    pub pairs: Vec<(Entity, Entity)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SkyEvent {
+    pub kind: SkyEventKind,
+    pub time: f64,
+    /// Simulated date of the event, from the starting time of the .sim file
+    pub date: NaiveDateTime,
+    /// The body and the star for conjunctions and oppositions, the pair for closest approaches.
+    /// The bodies might be gone later.
+    pub names: [String; 2],
+    pub observer: Option<String>,
+    /// Angle between the body and the star in degrees, or the distance in meters for closest approaches
+    pub value: f64,
+}
+
+impl SkyEvent {
+
+    pub fn description(&self) -> String {
+        let [first, second] = &self.names;
+        let observer = self.observer.as_deref().unwrap_or_default();
+        match self.kind {
+            SkyEventKind::Conjunction => format!("{} in conjunction with {}, seen from {}", first, second, observer),
+            SkyEventKind::Opposition => format!("{} in opposition to {}, seen from {}", first, second, observer),
+            SkyEventKind::ClosestApproach => format!("{} and {}", first, second),
+        }
+    }
+
+    pub fn value_text(&self) -> String {
+        match self.kind {
+            SkyEventKind::ClosestApproach => format!("{} ({:.4} au)", format_length(self.value as f32), self.value * M_TO_AU as f64),
+            _ => format!("{:.2}°", self.value),
+        }
+    }
+
+    pub fn date_text(&self) -> String {
+        self.date.format("%d.%m.%Y %H:%M").to_string()
+    }
+
+    /// Case-insensitive search in the type, the bodies and the date
+    pub fn matches(&self, search: &str) -> bool {
+        let search = search.to_lowercase();
+        [self.kind.name().to_string(), self.description(), self.date_text()].iter().any(|text| text.to_lowercase().contains(&search))
+    }
+
+}
+
+#[derive(Resource, Default)]
+pub struct SkyEventLog {
+    pub events: Vec<SkyEvent>,
+    /// Observer the longitudes were measured from
+    observer: Option<Entity>,
+    /// Time, longitude relative to the star and angle to the star of every body at the last tick
+    longitudes: HashMap<Entity, (f64, f64, f64)>,
+    /// Time and distance of every pair at the last three ticks, oldest first
+    distances: HashMap<(Entity, Entity), Vec<(f64, f64)>>,
+}
+
+impl SkyEventLog {
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.longitudes.clear();
+        self.distances.clear();
+    }
+
+}
+
+/// Looks for longitude crossings and distance minima after every physics tick, the exact time is interpolated between the ticks
+fn detect_sky_events(
+    bodies: Query<(Entity, &Name, &SimPosition, Option<&BodyParent>, &BodyKind), With<Mass>>,
+    mut log: ResMut<SkyEventLog>,
+    settings: Res<SkyEventSettings>,
+    pause: Res<Pause>,
+    sim_time: Res<SimTime>,
+    starting_time: Res<StartingTime>,
+) {
+    if pause.0 {
+        return;
+    }
+    let time = sim_time.0;
+    let mut found = vec![];
+    if log.observer != settings.observer {
+        log.longitudes.clear();
+        log.observer = settings.observer;
+    }
+    let parent_of = |entity: Entity| bodies.get(entity).ok().and_then(|(_, _, _, parent, _)| parent.map(|parent| parent.0));
+    let kind_of = |entity: Entity| bodies.get(entity).ok().map(|(.., kind)| *kind);
+
+    if let Some((observer, observer_name, observer_pos, ..)) = settings.observer.and_then(|observer| bodies.get(observer).ok()) {
+        //the first star above the observer, the observer itself can't be one
+        let mut star = parent_of(observer);
+        while let Some(entity) = star.filter(|entity| kind_of(*entity) != Some(BodyKind::Star)) {
+            star = parent_of(entity);
+        }
+        if let Some((star, star_name, star_pos, ..)) = star.and_then(|star| bodies.get(star).ok()) {
+            let to_star = star_pos.0 - observer_pos.0;
+            //the other planets of the star and the observer's own moons
+            let visible = bodies.iter().filter(|(entity, .., parent, kind)| {
+                *entity != observer && *entity != star && !matches!(kind, BodyKind::Star | BodyKind::Barycenter)
+                    && parent.is_some_and(|parent| parent.0 == observer || matches!(kind_of(parent.0), Some(BodyKind::Star | BodyKind::Barycenter)))
+            });
+            for (entity, name, position, ..) in visible {
+                let to_body = position.0 - observer_pos.0;
+                let longitude = wrap_angle(ecliptic_longitude(to_body) - ecliptic_longitude(to_star));
+                let separation = to_body.angle_between(to_star);
+                let Some((last_time, last_longitude, last_separation)) = log.longitudes.insert(entity, (time, longitude, separation)) else {
+                    continue;
+                };
+                if last_longitude.signum() == longitude.signum() || last_time >= time {
+                    continue;
+                }
+                let (kind, before, after) = if last_longitude.abs() < FRAC_PI_2 && longitude.abs() < FRAC_PI_2 {
+                    (SkyEventKind::Conjunction, last_longitude, longitude)
+                } else if last_longitude.abs() > FRAC_PI_2 && longitude.abs() > FRAC_PI_2 {
+                    (SkyEventKind::Opposition, wrap_angle(last_longitude + PI), wrap_angle(longitude + PI))
+                } else {
+                    continue;
+                };
+                let fraction = before / (before - after);
+                found.push(SkyEvent {
+                    kind,
+                    time: last_time + (time - last_time) * fraction,
+                    date: SimTime(last_time + (time - last_time) * fraction).date(&starting_time),
+                    names: [name.to_string(), star_name.to_string()],
+                    observer: Some(observer_name.to_string()),
+                    value: (last_separation + (separation - last_separation) * fraction).to_degrees(),
+                });
+            }
+        }
+    }
+
+    for (first, second) in &settings.pairs {
+        let (Ok((_, first_name, first_pos, ..)), Ok((_, second_name, second_pos, ..))) = (bodies.get(*first), bodies.get(*second)) else {
+            continue;
+        };
+        let samples = log.distances.entry((*first, *second)).or_default();
+        samples.push((time, first_pos.0.distance(second_pos.0)));
+        if samples.len() > 3 {
+            samples.remove(0);
+        }
+        let [oldest, middle, newest] = samples[..] else {
+            continue;
+        };
+        //bodies that keep their distance have no minimum
+        if middle.1 == oldest.1 {
+            continue;
+        }
+        if let Some((min_time, distance)) = parabola_minimum([oldest, middle, newest]) {
+            found.push(SkyEvent {
+                kind: SkyEventKind::ClosestApproach,
+                time: min_time,
+                date: SimTime(min_time).date(&starting_time),
+                names: [first_name.to_string(), second_name.to_string()],
+                observer: None,
+                value: distance,
+            });
+        }
+    }
+
+    for event in &found {
+        info!("{}: {} {} ({})", event.date_text(), event.kind.name(), event.description(), event.value_text());
+    }
+    log.events.extend(found);
+    if log.events.len() > MAX_EVENTS {
+        let removed = log.events.len() - MAX_EVENTS;
+        log.events.drain(..removed);
+    }
+}
+
+/// Angle in the ecliptic (the x-y plane) measured from the x axis
+fn ecliptic_longitude(direction: DVec3) -> f64 {
+    direction.y.atan2(direction.x)
+}
+
+/// The same angle between -π and π
+fn wrap_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// The events as comma separated values, one line per event
+fn to_csv(events: &[&SkyEvent]) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('"', "\"\""));
+    let mut csv = String::from("date,simulated_seconds,type,bodies,observer,value,unit\n");
+    for event in events {
+        let unit = if event.kind == SkyEventKind::ClosestApproach { "m" } else { "deg" };
+        csv.push_str(&format!("{},{:.0},{},{},{},{},{}\n",
+            event.date.format("%Y-%m-%d %H:%M:%S"),
+            event.time,
+            event.kind.name(),
+            quote(&event.names.join(" / ")),
+            quote(event.observer.as_deref().unwrap_or_default()),
+            event.value,
+            unit,
+        ));
+    }
+    csv
+}
+
+#[derive(Default)]
+struct SkyEventWindow {
+    search: String,
+    /// Pair that is about to be added
+    first: Option<Entity>,
+    second: Option<Entity>,
+    /// Result of the last export
+    status: String,
+}
+
+fn sky_event_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut log: ResMut<SkyEventLog>,
+    mut settings: ResMut<SkyEventSettings>,
+    mut window: Local<SkyEventWindow>,
+    bodies: Query<(Entity, &Name, &BodyKind), With<Mass>>,
+) {
+    if !ui_state.visible {
+        return;
+    }
+    let mut names = bodies.iter().filter(|(.., kind)| **kind != BodyKind::Barycenter).map(|(entity, name, _)| (entity, name.to_string())).collect::<Vec<_>>();
+    names.sort_by(|(_, first), (_, second)| first.cmp(second));
+    let name_of = |entity: Option<Entity>| entity.and_then(|entity| names.iter().find(|(other, _)| *other == entity)).map(|(_, name)| name.clone()).unwrap_or_else(|| "None".to_string());
+    let body_combo = |ui: &mut egui::Ui, id: &str, value: &mut Option<Entity>| {
+        egui::ComboBox::from_id_source(id)
+            .selected_text(name_of(*value))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(value, None, "None");
+                for (entity, name) in &names {
+                    ui.selectable_value(value, Some(*entity), name);
+                }
+            });
+    };
+    let mut clear = false;
+    egui::Window::new("Sky Events")
+        .open(&mut ui_state.show_sky_events)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Conjunctions and Oppositions seen from");
+                body_combo(ui, "sky_observer", &mut settings.observer);
+            });
+            ui.label("Closest Approaches");
+            let mut removed = None;
+            for (index, (first, second)) in settings.pairs.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} and {}", name_of(Some(*first)), name_of(Some(*second))));
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                let pair = settings.pairs.remove(index);
+                log.distances.remove(&pair);
+            }
+            ui.horizontal(|ui| {
+                body_combo(ui, "sky_first", &mut window.first);
+                body_combo(ui, "sky_second", &mut window.second);
+                if let (Some(first), Some(second)) = (window.first, window.second) {
+                    if first != second && !settings.pairs.contains(&(first, second)) && ui.button("Add Pair").clicked() {
+                        settings.pairs.push((first, second));
+                    }
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Search");
+                ui.text_edit_singleline(&mut window.search);
+            });
+            let shown = log.events.iter().rev().filter(|event| event.matches(&window.search)).collect::<Vec<_>>();
+            ui.horizontal(|ui| {
+                if ui.button("Export CSV").clicked() {
+                    window.status = match std::fs::write(EXPORT_PATH, to_csv(&shown)) {
+                        Ok(_) => format!("Exported {} events to {}", shown.len(), EXPORT_PATH),
+                        Err(e) => {
+                            error!("Couldn't export the sky events: {}", e);
+                            format!("Export failed: {}", e)
+                        }
+                    };
+                }
+                if ui.button("Copy").clicked() {
+                    ui.output_mut(|output| output.copied_text = to_csv(&shown));
+                    window.status = format!("Copied {} events", shown.len());
+                }
+                if ui.button("Clear").clicked() {
+                    window.status.clear();
+                    clear = true;
+                }
+            });
+            if !window.status.is_empty() {
+                ui.label(&window.status);
+            }
+            if shown.is_empty() {
+                ui.label("No events yet");
+            }
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                egui::Grid::new("sky_events").striped(true).show(ui, |ui| {
+                    for header in ["Date", "Type", "Bodies", "Value"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for event in &shown {
+                        ui.label(event.date_text());
+                        ui.label(event.kind.name());
+                        ui.label(event.description());
+                        ui.label(event.value_text());
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    if clear {
+        log.clear();
+    }
+}
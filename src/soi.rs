@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use bevy::app::{App, FixedUpdate, Plugin, Update};
+use bevy::core::Name;
+use bevy::log::info;
+use bevy::math::DVec3;
+use bevy::prelude::{Commands, Entity, Event, EventReader, EventWriter, Has, in_state, IntoSystemConfigs, Query, Res, ResMut, Resource};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::apsis::ApsisBody;
+use crate::barycenter::{Barycenter, update_barycenters};
+use crate::body::{BodyChildren, BodyParent, Mass, OrbitSettings, SimPosition, Star};
+use crate::physics::{Pause, SimTime};
+use crate::setup::StartingTime;
+use crate::SimState;
+use crate::ui::{system_ui, UiState};
+
+/// A body only leaves its parent once it is this much farther out than the sphere, so it doesn't switch back and forth at the border
+const ESCAPE_MARGIN: f64 = 1.1;
+
+pub struct SoiPlugin;
+
+impl Plugin for SoiPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<SoiSettings>()
+            .init_resource::<SoiLog>()
+            .add_event::<ParentChange>()
+            .add_systems(FixedUpdate, (update_parents.after(update_barycenters), record_parent_changes.after(update_parents)).run_if(in_state(SimState::Simulation)))
+            .add_systems(Update, (soi_window.after(system_ui)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+/// Decides when a body gets a new parent
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SoiMode {
+    /// The parents from the .sim file are kept forever
+    Off,
+    /// Laplace sphere of influence, r = a (m / M)^(2/5)
+    #[default]
+    SphereOfInfluence,
+    /// Hill sphere, r = a (m / 3M)^(1/3)
+    HillSphere,
+}
+
+impl SoiMode {
+
+    pub const ALL: [SoiMode; 3] = [SoiMode::Off, SoiMode::SphereOfInfluence, SoiMode::HillSphere];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SoiMode::Off => "Off",
+            SoiMode::SphereOfInfluence => "Sphere of Influence",
+            SoiMode::HillSphere => "Hill Sphere",
+        }
+    }
+
+    /// Radius of the sphere of a body with `mass` at `distance` from its parent, empty if the parent has no mass
+    pub fn radius(&self, mass: f64, parent_mass: f64, distance: f64) -> f64 {
+        if parent_mass <= 0.0 {
+            return 0.0;
+        }
+        match self {
+            SoiMode::Off => 0.0,
+            SoiMode::SphereOfInfluence => distance * (mass / parent_mass).powf(0.4),
+            SoiMode::HillSphere => distance * (mass / (3.0 * parent_mass)).cbrt(),
+        }
+    }
+
+}
+
+#[derive(Resource, Default)]
+pub struct SoiSettings {
+    pub mode: SoiMode,
+}
+
+#[derive(Resource, Default)]
+pub struct SoiLog {
+    pub entries: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParentChangeKind {
+    /// The body entered the sphere of a body that isn't one of the ancestors of its old parent
+    Capture,
+    /// The body left its parent for one of the parent's ancestors
+    Escape,
+}
+
+/// Sent whenever a body switches its parent
+#[derive(Event, Debug, Clone)]
+pub struct ParentChange {
+    pub body: Entity,
+    pub old_parent: Entity,
+    pub new_parent: Entity,
+    pub kind: ParentChangeKind,
+    pub time: f64,
+}
+
+/// Moves every body to the smallest sphere it is inside of. Stars and barycenters keep their parents.
+fn update_parents(
+    mut commands: Commands,
+    bodies: Query<(Entity, &Mass, &SimPosition, Option<&BodyParent>, Has<Star>, Has<Barycenter>)>,
+    mut children: Query<&mut BodyChildren>,
+    mut events: EventWriter<ParentChange>,
+    settings: Res<SoiSettings>,
+    pause: Res<Pause>,
+    sim_time: Res<SimTime>,
+) {
+    if settings.mode == SoiMode::Off || pause.0 {
+        return;
+    }
+    let parents: HashMap<Entity, Entity> = bodies.iter().filter_map(|(entity, _, _, parent, ..)| parent.map(|parent| (entity, parent.0))).collect();
+    //a body at the top of the hierarchy is the parent of everything that isn't inside a smaller sphere
+    let spheres: Vec<(Entity, DVec3, f64)> = bodies.iter().filter(|(_, mass, ..)| mass.0 > 0.0).map(|(entity, mass, position, parent, ..)| {
+        let radius = match parent.and_then(|parent| bodies.get(parent.0).ok()) {
+            Some((_, p_mass, p_position, ..)) => settings.mode.radius(mass.0, p_mass.0, position.0.distance(p_position.0)),
+            None => f64::INFINITY,
+        };
+        (entity, position.0, radius)
+    }).collect();
+
+    let mut changes = vec![];
+    for (entity, _, position, parent, star, barycenter) in &bodies {
+        let Some(parent) = parent.map(|parent| parent.0) else {
+            continue;
+        };
+        if star || barycenter {
+            continue;
+        }
+        let new_parent = spheres.iter()
+            .filter(|(other, ..)| *other != entity && !is_ancestor(entity, *other, &parents))
+            .filter(|(other, other_position, radius)| {
+                let margin = if *other == parent { ESCAPE_MARGIN } else { 1.0 };
+                position.0.distance(*other_position) < radius * margin
+            })
+            .min_by(|(_, _, first), (_, _, second)| first.total_cmp(second))
+            .map(|(other, ..)| *other);
+        if let Some(new_parent) = new_parent.filter(|new_parent| *new_parent != parent) {
+            changes.push((entity, parent, new_parent));
+        }
+    }
+
+    //parents without children yet get all their new ones at once, separate inserts would overwrite each other
+    let mut first_children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for (entity, old_parent, new_parent) in changes {
+        commands.entity(entity).insert(BodyParent(new_parent));
+        if let Ok(mut old_children) = children.get_mut(old_parent) {
+            old_children.0.retain(|child| *child != entity);
+        }
+        match children.get_mut(new_parent) {
+            Ok(mut new_children) => new_children.0.push(entity),
+            Err(_) => first_children.entry(new_parent).or_default().push(entity),
+        }
+        let kind = if is_ancestor(new_parent, old_parent, &parents) { ParentChangeKind::Escape } else { ParentChangeKind::Capture };
+        events.send(ParentChange {
+            body: entity,
+            old_parent,
+            new_parent,
+            kind,
+            time: sim_time.0,
+        });
+    }
+    for (parent, new_children) in first_children {
+        commands.entity(parent).insert(BodyChildren(new_children));
+    }
+}
+
+/// Logs the parent changes and restarts the orbit lines and apsis tracking, which are relative to the parent
+fn record_parent_changes(
+    mut commands: Commands,
+    mut events: EventReader<ParentChange>,
+    mut tracking: Query<(&mut OrbitSettings, Option<&mut ApsisBody>)>,
+    names: Query<&Name>,
+    mut log: ResMut<SoiLog>,
+    starting_time: Res<StartingTime>,
+) {
+    for event in events.read() {
+        if let Ok((mut orbit, apsis)) = tracking.get_mut(event.body) {
+            orbit.lines.clear();
+            match apsis {
+                Some(mut apsis) => *apsis = ApsisBody::default(),
+                None => { commands.entity(event.body).insert(ApsisBody::default()); }
+            }
+        }
+        let name = |entity: Entity| names.get(entity).map(|name| name.to_string()).unwrap_or_default();
+        let date = SimTime(event.time).date(&starting_time).format("%d.%m.%Y %H:%M");
+        let entry = match event.kind {
+            ParentChangeKind::Capture => format!("{}: {} was captured by {} (left {})", date, name(event.body), name(event.new_parent), name(event.old_parent)),
+            ParentChangeKind::Escape => format!("{}: {} escaped from {} and now orbits {}", date, name(event.body), name(event.old_parent), name(event.new_parent)),
+        };
+        info!("{}", entry);
+        log.entries.push(entry);
+    }
+}
+
+/// Whether `ancestor` is somewhere above `entity` in the hierarchy
+fn is_ancestor(ancestor: Entity, entity: Entity, parents: &HashMap<Entity, Entity>) -> bool {
+    let mut current = entity;
+    while let Some(parent) = parents.get(&current) {
+        if *parent == ancestor {
+            return true;
+        }
+        current = *parent;
+    }
+    false
+}
+
+fn soi_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    log: Res<SoiLog>,
+) {
+    if !ui_state.visible {
+        return;
+    }
+    egui::Window::new("Captures and Escapes")
+        .open(&mut ui_state.show_captures)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            if log.entries.is_empty() {
+                ui.label("No body changed its parent yet");
+            }
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for entry in log.entries.iter().rev() {
+                    ui.label(entry);
+                }
+            });
+        });
+}
@@ -0,0 +1,541 @@
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::app::{App, FixedUpdate, Plugin, Update};
+use bevy::asset::AssetServer;
+use bevy::core::Name;
+use bevy::log::info;
+use bevy::math::{DQuat, DVec3};
+use bevy::pbr::StandardMaterial;
+use bevy::prelude::{Assets, Commands, Component, Entity, Has, in_state, IntoSystemConfigs, Mesh, Query, Res, ResMut, Resource, Time, With};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::apsis::OsculatingElements;
+use crate::barycenter::{Barycenter, orbit_gm};
+use crate::body::{BodyKind, BodyParent, Mass, SimPosition, Star, Velocity};
+use crate::constants::M_TO_AU;
+use crate::kepler::OrbitalElements;
+use crate::maneuver::{Burn, global_to_local, ManeuverSchedule};
+use crate::physics::{apply_physics, SimTime};
+use crate::setup::{spawn_massless_body, StartingTime};
+use crate::SimState;
+use crate::soi::SoiMode;
+use crate::ui::{system_ui, UiState};
+use crate::unit::{format_length, format_seconds};
+
+/// The spacecraft starts this many spheres of influence ahead of the origin on its orbit, so it doesn't start inside of it
+const CLEARANCE: f64 = 2.0;
+/// Departure times that are tried before the exact one is found by bisection
+const SEARCH_STEPS: usize = 720;
+/// Real seconds after which the plan is made again from the current orbits
+const REPLAN_INTERVAL: f64 = 1.0;
+
+pub struct TransferPlugin;
+
+impl Plugin for TransferPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<TransferPlanner>()
+            .add_systems(FixedUpdate, (launch_transfer.after(apply_physics), track_transfers.after(launch_transfer)).run_if(in_state(SimState::Simulation)))
+            .add_systems(Update, (transfer_window.after(system_ui)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    /// Half an ellipse touching both orbits, two burns
+    #[default]
+    Hohmann,
+    /// Out to a far apoapsis first and back to the target orbit on a second ellipse, three burns.
+    /// Cheaper than a Hohmann transfer for targets more than about 12 times farther out.
+    BiElliptic,
+}
+
+impl TransferKind {
+
+    pub const ALL: [TransferKind; 2] = [TransferKind::Hohmann, TransferKind::BiElliptic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransferKind::Hohmann => "Hohmann",
+            TransferKind::BiElliptic => "Bi-elliptic",
+        }
+    }
+
+    /// Names of the burns, the first one is the injection
+    pub fn burn_names(&self) -> &'static [&'static str] {
+        match self {
+            TransferKind::Hohmann => &["Departure", "Arrival"],
+            TransferKind::BiElliptic => &["Departure", "Apoapsis", "Arrival"],
+        }
+    }
+
+}
+
+/// Transfer on the osculating orbits of two bodies, the burns are tangential and the arrival is where the target will be
+#[derive(Debug, Clone)]
+pub struct TransferPlan {
+    pub kind: TransferKind,
+    /// Simulated time of every burn, the first one is the departure and the last one the arrival
+    pub burn_times: Vec<f64>,
+    /// Δv of every burn in the local frame of the spacecraft's orbit right before it, like [`Burn::delta_v`]
+    pub burns: Vec<DVec3>,
+    /// Distance of the origin from the parent at the departure and of the target at the arrival
+    pub origin_radius: f64,
+    pub target_radius: f64,
+    /// Farthest point of the transfer from the parent
+    pub apoapsis: f64,
+    /// Angle the target is ahead of the origin now and at the departure
+    pub phase: f64,
+    pub required_phase: f64,
+    /// Angle the spacecraft starts ahead of the origin on its orbit
+    pub lead: f64,
+}
+
+impl TransferPlan {
+
+    pub fn departure(&self) -> f64 {
+        self.burn_times[0]
+    }
+
+    pub fn arrival(&self) -> f64 {
+        *self.burn_times.last().unwrap()
+    }
+
+    pub fn time_of_flight(&self) -> f64 {
+        self.arrival() - self.departure()
+    }
+
+    pub fn total_delta_v(&self) -> f64 {
+        self.burns.iter().map(|burn| burn.length()).sum()
+    }
+
+    /// The burns after the injection, the arrival matches the velocity of the target as planned and isn't only along the orbit
+    pub fn later_burns(&self) -> Vec<Burn> {
+        self.burn_times.iter().zip(&self.burns).skip(1).map(|(time, delta_v)| Burn::impulsive(*time, *delta_v)).collect()
+    }
+
+    /// Position and velocity relative to the parent right after the departure burn, from the origin's state relative to the parent.
+    /// The burn is tangential at the current distance, so the first turning point is exactly at the planned radius.
+    pub fn injection(&self, gm: f64, origin_position: DVec3, origin_velocity: DVec3) -> (DVec3, DVec3) {
+        let normal = origin_position.cross(origin_velocity).normalize();
+        let position = DQuat::from_axis_angle(normal, self.lead) * origin_position;
+        let radius = position.length();
+        let far_point = match self.kind {
+            TransferKind::Hohmann => self.target_radius,
+            TransferKind::BiElliptic => self.apoapsis,
+        };
+        let speed = vis_viva(gm, radius, (radius + far_point) / 2.0);
+        (position, normal.cross(position / radius) * speed)
+    }
+
+}
+
+/// Orbital speed at `radius` on an orbit with the semi-major axis `a`
+fn vis_viva(gm: f64, radius: f64, a: f64) -> f64 {
+    (gm * (2.0 / radius - 1.0 / a)).sqrt()
+}
+
+/// Angle from `from` to `to` around `normal`, between -π and π
+fn signed_angle(from: DVec3, to: DVec3, normal: DVec3) -> f64 {
+    from.cross(to).dot(normal).atan2(from.dot(to))
+}
+
+/// Shape of a transfer that departs at `time` seconds from now
+struct Geometry {
+    start: DVec3,
+    start_velocity: DVec3,
+    normal: DVec3,
+    arrival_direction: DVec3,
+    target_radius: f64,
+    apoapsis: f64,
+    /// Semi-major axes of the ellipses the spacecraft flies on
+    ellipses: Vec<f64>,
+    time_of_flight: f64,
+}
+
+impl Geometry {
+
+    fn new(kind: TransferKind, gm: f64, (origin, origin_gm): (&OrbitalElements, f64), target: &OrbitalElements, apoapsis_factor: f64, lead: f64, time: f64) -> Geometry {
+        let (position, velocity) = origin.propagate(origin_gm, time).to_state(origin_gm);
+        let normal = position.cross(velocity).normalize();
+        let rotation = DQuat::from_axis_angle(normal, lead);
+        let (start, start_velocity) = (rotation * position, rotation * velocity);
+        let origin_radius = start.length();
+        //a Hohmann transfer arrives opposite of the start, a bi-elliptic one where it started after a full turn
+        let arrival_direction = match kind {
+            TransferKind::Hohmann => -start / origin_radius,
+            TransferKind::BiElliptic => start / origin_radius,
+        };
+        let target_radius = target.radius_towards(arrival_direction);
+        let (apoapsis, ellipses) = match kind {
+            TransferKind::Hohmann => (origin_radius.max(target_radius), vec![(origin_radius + target_radius) / 2.0]),
+            TransferKind::BiElliptic => {
+                let apoapsis = origin_radius.max(target_radius) * apoapsis_factor;
+                (apoapsis, vec![(origin_radius + apoapsis) / 2.0, (target_radius + apoapsis) / 2.0])
+            }
+        };
+        let time_of_flight = ellipses.iter().map(|a| PI * (a.powi(3) / gm).sqrt()).sum();
+        Geometry { start, start_velocity, normal, arrival_direction, target_radius, apoapsis, ellipses, time_of_flight }
+    }
+
+    /// How far the target misses the arrival point, as an angle around the parent
+    fn miss(&self, (target, target_gm): (&OrbitalElements, f64), time: f64) -> f64 {
+        let (target_position, _) = target.propagate(target_gm, time + self.time_of_flight).to_state(target_gm);
+        signed_angle(self.arrival_direction, target_position, self.normal)
+    }
+
+}
+
+/// Plans a transfer between two bodies on bound osculating orbits around the same parent. `gm` is the one of the parent alone that the massless spacecraft flies with,
+/// the elements of the origin and the target come with the gm they were computed with (see [`orbit_gm`]).
+/// Both orbits are propagated to find the first departure from which the spacecraft meets the target.
+/// The apoapsis of a bi-elliptic transfer is given in multiples of the larger radius. The spacecraft starts `lead` ahead of the origin.
+pub fn plan_transfer(
+    kind: TransferKind,
+    gm: f64,
+    (origin, origin_gm): (&OrbitalElements, f64),
+    (target, target_gm): (&OrbitalElements, f64),
+    apoapsis_factor: f64,
+    lead: f64,
+    now: f64,
+) -> Option<TransferPlan> {
+    if origin.eccentricity >= 1.0 || target.eccentricity >= 1.0 || origin.semi_major_axis <= 0.0 || target.semi_major_axis <= 0.0 {
+        return None;
+    }
+    let motion = |elements: &OrbitalElements, gm: f64| (gm / elements.semi_major_axis.powi(3)).sqrt();
+    let relative_motion = (motion(target, target_gm) - motion(origin, origin_gm)).abs();
+    if relative_motion == 0.0 {
+        return None;
+    }
+    //the first time the miss changes its sign within one and a half synodic periods, away from the jump at ±π
+    let search = 1.5 * TAU / relative_motion;
+    let miss = |time: f64| Geometry::new(kind, gm, (origin, origin_gm), target, apoapsis_factor, lead, time).miss((target, target_gm), time);
+    let (mut before, mut after) = (0.0, 0.0);
+    let mut last = miss(0.0);
+    let found = (1..=SEARCH_STEPS).any(|step| {
+        let time = search * step as f64 / SEARCH_STEPS as f64;
+        let current = miss(time);
+        let crossed = current.signum() != last.signum() && current.abs() < FRAC_PI_2 && last.abs() < FRAC_PI_2;
+        (before, after, last) = (time - search / SEARCH_STEPS as f64, time, current);
+        crossed
+    });
+    if !found {
+        return None;
+    }
+    for _ in 0..60 {
+        let middle = (before + after) / 2.0;
+        if miss(middle).signum() == miss(before).signum() {
+            before = middle;
+        } else {
+            after = middle;
+        }
+    }
+    let departure = (before + after) / 2.0;
+    let geometry = Geometry::new(kind, gm, (origin, origin_gm), target, apoapsis_factor, lead, departure);
+    let origin_radius = geometry.start.length();
+    let tangential = |position: DVec3, speed: f64| geometry.normal.cross(position.normalize()) * speed;
+    let (_, target_velocity) = target.propagate(target_gm, departure + geometry.time_of_flight).to_state(target_gm);
+    let arrival_position = geometry.arrival_direction * geometry.target_radius;
+    let last_ellipse = *geometry.ellipses.last().unwrap();
+    let injection = tangential(geometry.start, vis_viva(gm, origin_radius, geometry.ellipses[0])) - geometry.start_velocity;
+    let mut burns = vec![global_to_local(injection, geometry.start, geometry.start_velocity)];
+    let mut burn_times = vec![now + departure];
+    if kind == TransferKind::BiElliptic {
+        //tangential at the apoapsis, faster on the second ellipse if the target is farther out
+        burns.push(DVec3::X * (vis_viva(gm, geometry.apoapsis, geometry.ellipses[1]) - vis_viva(gm, geometry.apoapsis, geometry.ellipses[0])));
+        burn_times.push(now + departure + PI * (geometry.ellipses[0].powi(3) / gm).sqrt());
+    }
+    //the transfer goes the same way around as the origin, at the arrival it flies along the opposite of the start direction for a Hohmann transfer
+    let arrival_velocity = tangential(arrival_position, vis_viva(gm, geometry.target_radius, last_ellipse));
+    burns.push(global_to_local(target_velocity - arrival_velocity, arrival_position, arrival_velocity));
+    burn_times.push(now + departure + geometry.time_of_flight);
+
+    let (origin_now, _) = origin.to_state(origin_gm);
+    let (target_now, _) = target.to_state(target_gm);
+    let (target_then, _) = target.propagate(target_gm, departure).to_state(target_gm);
+    Some(TransferPlan {
+        kind,
+        burn_times,
+        burns,
+        origin_radius,
+        target_radius: geometry.target_radius,
+        apoapsis: geometry.apoapsis,
+        phase: signed_angle(origin_now, target_now, geometry.normal).rem_euclid(TAU),
+        required_phase: signed_angle(geometry.start, target_then, geometry.normal).rem_euclid(TAU),
+        lead,
+    })
+}
+
+#[derive(Resource)]
+pub struct TransferPlanner {
+    pub origin: Option<Entity>,
+    pub target: Option<Entity>,
+    pub kind: TransferKind,
+    /// Apoapsis of a bi-elliptic transfer in multiples of the larger radius
+    pub apoapsis_factor: f64,
+    /// Spacecraft that is spawned once its departure comes, from this planner or the porkchop plot
+    pub pending: Option<Launch>,
+    /// Plan for the current choice, see [`REPLAN_INTERVAL`]
+    pub plan: Option<TransferPlan>,
+    planned_for: Option<(Option<Entity>, Option<Entity>, TransferKind, f64)>,
+    /// Real seconds since the start of the app
+    planned_at: f64,
+}
+
+impl Default for TransferPlanner {
+
+    fn default() -> Self {
+        TransferPlanner { origin: None, target: None, kind: TransferKind::Hohmann, apoapsis_factor: 2.0, pending: None, plan: None, planned_for: None, planned_at: 0.0 }
+    }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Launch {
+    pub origin: Entity,
+    pub target: Entity,
+    pub departure: f64,
+    pub arrival: f64,
+    pub trajectory: Trajectory,
+}
+
+#[derive(Debug, Clone)]
+pub enum Trajectory {
+    /// The tangential injection of a planned transfer
+    Planned(TransferPlan),
+    /// Velocity relative to the parent from a Lambert solution, starting `lead` ahead of the origin on its orbit
+    Lambert { velocity: DVec3, lead: f64 },
+}
+
+impl Trajectory {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trajectory::Planned(plan) => plan.kind.name(),
+            Trajectory::Lambert { .. } => "Lambert",
+        }
+    }
+
+}
+
+/// A spacecraft that was spawned by the planner, to compare its flight with the plan
+#[derive(Component, Debug, Clone)]
+pub struct TransferSpacecraft {
+    pub target: Entity,
+    /// Planned arrival at the target
+    pub arrival: f64,
+    /// Smallest distance to the target so far and when it was reached
+    pub closest: Option<(f64, f64)>,
+}
+
+/// The plan for the bodies chosen in the planner, from their osculating orbits
+fn current_plan(
+    planner: &TransferPlanner,
+    bodies: &Query<(Entity, &Name, &Mass, &SimPosition, &Velocity, &BodyParent, &OsculatingElements, Has<Star>)>,
+    parents: &Query<(&Mass, &SimPosition, &Velocity, Has<Barycenter>)>,
+    now: f64,
+) -> Option<TransferPlan> {
+    let (_, _, mass, _, _, parent, origin, origin_star) = bodies.get(planner.origin?).ok()?;
+    let (_, _, target_mass, _, _, target_parent, target, target_star) = bodies.get(planner.target?).ok()?;
+    if parent.0 != target_parent.0 || planner.origin == planner.target {
+        return None;
+    }
+    let (p_mass, _, _, barycenter) = parents.get(parent.0).ok()?;
+    //the same gm the osculating elements were computed with, the spacecraft itself is massless
+    let origin_gm = orbit_gm(p_mass.0, mass.0, barycenter && origin_star);
+    let target_gm = orbit_gm(p_mass.0, target_mass.0, barycenter && target_star);
+    let lead = departure_lead(mass.0, p_mass.0, origin.elements.semi_major_axis);
+    plan_transfer(planner.kind, orbit_gm(p_mass.0, 0.0, false), (&origin.elements, origin_gm), (&target.elements, target_gm), planner.apoapsis_factor, lead, now)
+}
+
+/// Angle a spacecraft starts ahead of a body with `mass` on its orbit with the `radius`, so it is outside of its sphere of influence
+pub fn departure_lead(mass: f64, parent_mass: f64, radius: f64) -> f64 {
+    CLEARANCE * SoiMode::SphereOfInfluence.radius(mass, parent_mass, radius) / radius
+}
+
+/// Spawns the spacecraft of the pending transfer right after the physics tick that reached the departure
+fn launch_transfer(
+    mut commands: Commands,
+    mut planner: ResMut<TransferPlanner>,
+    bodies: Query<(Entity, &Name, &Mass, &SimPosition, &Velocity, &BodyParent, &OsculatingElements)>,
+    parents: Query<(&Mass, &SimPosition, &Velocity)>,
+    sim_time: Res<SimTime>,
+    assets: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !planner.pending.as_ref().is_some_and(|launch| sim_time.0 >= launch.departure) {
+        return;
+    }
+    let launch = planner.pending.take().unwrap();
+    let (Ok((_, origin_name, _, pos, vel, parent, _)), Ok((_, target_name, ..))) = (bodies.get(launch.origin), bodies.get(launch.target)) else {
+        return;
+    };
+    let Ok((p_mass, p_pos, p_vel)) = parents.get(parent.0) else {
+        return;
+    };
+    let (origin_position, origin_velocity) = (pos.0 - p_pos.0, vel.0 - p_vel.0);
+    let (position, velocity) = match &launch.trajectory {
+        Trajectory::Planned(plan) => plan.injection(orbit_gm(p_mass.0, 0.0, false), origin_position, origin_velocity),
+        Trajectory::Lambert { velocity, lead } => (DQuat::from_axis_angle(origin_position.cross(origin_velocity).normalize(), *lead) * origin_position, *velocity),
+    };
+    info!("{} to {} transfer departed, {:.1} m/s injection burn", origin_name, target_name, (velocity - origin_velocity).length());
+    let name = format!("{} to {} ({})", origin_name, target_name, launch.trajectory.name());
+    let spacecraft = spawn_massless_body(name, BodyKind::Spacecraft, p_pos.0 + position, p_vel.0 + velocity, parent.0, &mut commands, &assets, &mut meshes, &mut materials);
+    commands.entity(spacecraft).insert(TransferSpacecraft { target: launch.target, arrival: launch.arrival, closest: None });
+    if let Trajectory::Planned(plan) = &launch.trajectory {
+        commands.entity(spacecraft).insert(ManeuverSchedule::new(plan.later_burns()));
+    }
+}
+
+fn track_transfers(
+    mut spacecraft: Query<(&SimPosition, &mut TransferSpacecraft)>,
+    positions: Query<&SimPosition, With<Mass>>,
+    sim_time: Res<SimTime>,
+) {
+    for (position, mut transfer) in &mut spacecraft {
+        let Ok(target) = positions.get(transfer.target) else {
+            continue;
+        };
+        let distance = position.0.distance(target.0);
+        if transfer.closest.map_or(true, |(closest, _)| distance < closest) {
+            transfer.closest = Some((distance, sim_time.0));
+        }
+    }
+}
+
+fn transfer_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut planner: ResMut<TransferPlanner>,
+    bodies: Query<(Entity, &Name, &Mass, &SimPosition, &Velocity, &BodyParent, &OsculatingElements, Has<Star>)>,
+    parents: Query<(&Mass, &SimPosition, &Velocity, Has<Barycenter>)>,
+    spacecraft: Query<(&Name, &SimPosition, &TransferSpacecraft)>,
+    sim_time: Res<SimTime>,
+    starting_time: Res<StartingTime>,
+    time: Res<Time>,
+) {
+    if !ui_state.visible {
+        return;
+    }
+    let date = |time: f64| SimTime(time).date(&starting_time).format("%d.%m.%Y %H:%M").to_string();
+    let mut names = bodies.iter().map(|(entity, name, ..)| (entity, name.to_string())).collect::<Vec<_>>();
+    names.sort_by(|(_, first), (_, second)| first.cmp(second));
+    let name_of = |entity: Option<Entity>| entity.and_then(|entity| names.iter().find(|(other, _)| *other == entity)).map(|(_, name)| name.clone()).unwrap_or_else(|| "None".to_string());
+    let parent_of = |entity: Option<Entity>| entity.and_then(|entity| bodies.get(entity).ok()).map(|(.., parent, _, _)| parent.0);
+    //the search takes hundreds of propagations, so it only runs again for a new choice or after a while
+    let choice = (planner.origin, planner.target, planner.kind, planner.apoapsis_factor);
+    if ui_state.show_transfers && (planner.planned_for != Some(choice) || time.elapsed_seconds_f64() - planner.planned_at > REPLAN_INTERVAL) {
+        planner.plan = current_plan(&planner, &bodies, &parents, sim_time.0);
+        planner.planned_for = Some(choice);
+        planner.planned_at = time.elapsed_seconds_f64();
+    }
+    let plan = planner.plan.clone();
+    egui::Window::new("Transfer Planner")
+        .open(&mut ui_state.show_transfers)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            let (origin, origin_parent) = (planner.origin, parent_of(planner.origin));
+            ui.horizontal(|ui| {
+                ui.label("From");
+                egui::ComboBox::from_id_source("transfer_origin")
+                    .selected_text(name_of(planner.origin))
+                    .show_ui(ui, |ui| {
+                        for (entity, name) in &names {
+                            ui.selectable_value(&mut planner.origin, Some(*entity), name);
+                        }
+                    });
+                ui.label("To");
+                //only bodies that orbit the same parent
+                egui::ComboBox::from_id_source("transfer_target")
+                    .selected_text(name_of(planner.target))
+                    .show_ui(ui, |ui| {
+                        for (entity, name) in names.iter().filter(|(entity, _)| parent_of(Some(*entity)) == origin_parent && Some(*entity) != origin) {
+                            ui.selectable_value(&mut planner.target, Some(*entity), name);
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                for option in TransferKind::ALL {
+                    ui.selectable_value(&mut planner.kind, option, option.name());
+                }
+                if planner.kind == TransferKind::BiElliptic {
+                    ui.label("Apoapsis");
+                    ui.add(egui::DragValue::new(&mut planner.apoapsis_factor).speed(0.1).clamp_range(1.0..=100.0).suffix("x"));
+                }
+            });
+            ui.separator();
+            let Some(plan) = plan else {
+                ui.label("Choose two bodies on bound orbits around the same parent");
+                return;
+            };
+            egui::Grid::new("transfer_plan").show(ui, |ui| {
+                ui.label("Orbit Radii");
+                ui.label(format!("{:.3} au to {:.3} au", plan.origin_radius * M_TO_AU as f64, plan.target_radius * M_TO_AU as f64));
+                ui.end_row();
+                if plan.kind == TransferKind::BiElliptic {
+                    ui.label("Apoapsis");
+                    ui.label(format!("{:.3} au", plan.apoapsis * M_TO_AU as f64));
+                    ui.end_row();
+                }
+                ui.label("Phase Angle");
+                ui.label(format!("{:.1}° (needs {:.1}°)", plan.phase.to_degrees(), plan.required_phase.to_degrees()));
+                ui.end_row();
+                ui.label("Departure");
+                ui.label(format!("{} (in {})", date(plan.departure()), format_seconds(plan.departure() - sim_time.0)));
+                ui.end_row();
+                ui.label("Arrival");
+                ui.label(date(plan.arrival()));
+                ui.end_row();
+                ui.label("Time of Flight");
+                ui.label(format_seconds(plan.time_of_flight()));
+                ui.end_row();
+                for (name, burn) in plan.kind.burn_names().iter().zip(&plan.burns) {
+                    ui.label(format!("Δv {}", name));
+                    ui.label(format!("{:.3} km/s", burn.length() / 1000.0));
+                    ui.end_row();
+                }
+                ui.label("Total Δv");
+                ui.label(format!("{:.3} km/s", plan.total_delta_v() / 1000.0));
+                ui.end_row();
+            });
+            match planner.pending.as_ref().map(|launch| launch.departure) {
+                Some(departure) => {
+                    ui.label(format!("Spacecraft departs on {}", date(departure)));
+                    if ui.button("Cancel").clicked() {
+                        planner.pending = None;
+                    }
+                }
+                None => {
+                    //the shown plan can be a second old, at high speeds the departure could already have passed
+                    if ui.button("Spawn Spacecraft at Departure").clicked() {
+                        if let Some(plan) = current_plan(&planner, &bodies, &parents, sim_time.0) {
+                            planner.pending = Some(Launch {
+                                origin: planner.origin.unwrap(),
+                                target: planner.target.unwrap(),
+                                departure: plan.departure(),
+                                arrival: plan.arrival(),
+                                trajectory: Trajectory::Planned(plan.clone()),
+                            });
+                            planner.plan = Some(plan);
+                        }
+                    }
+                }
+            }
+            if !spacecraft.is_empty() {
+                ui.separator();
+                ui.label("Planned vs. simulated arrival");
+            }
+            for (name, position, transfer) in &spacecraft {
+                ui.label(egui::RichText::new(name.as_str()).strong());
+                ui.label(format!("Planned arrival {}", date(transfer.arrival)));
+                if let Ok((_, _, _, target_pos, ..)) = bodies.get(transfer.target) {
+                    ui.label(format!("Distance to target {}", format_length(position.0.distance(target_pos.0) as f32)));
+                }
+                if let Some((distance, time)) = transfer.closest {
+                    ui.label(format!("Closest {} on {}", format_length(distance as f32), date(time)));
+                }
+            }
+        });
+}
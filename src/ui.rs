@@ -2,30 +2,33 @@ use bevy::{
     core_pipeline::Skybox,
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
     prelude::{
-        App, Camera, Color, Commands, DespawnRecursiveExt, Entity, GizmoConfig,
-        Input, IntoSystemConfigs, KeyCode, Mut, Name, NextState, Plugin, PointLight, Query, Res, ResMut, Resource, Transform, Vec3, Visibility, With, Without,
+        App, Camera, Color, Commands, DespawnRecursiveExt, Entity, EventWriter, GizmoConfig,
+        Input, IntoSystemConfigs, KeyCode, Mut, Name, NextState, Plugin, PointLight, Query, Res, ResMut, Resource, Transform, Vec3, With, Without,
     },
-    reflect::Reflect, time::Time, window::PresentMode,
+    reflect::Reflect, window::PresentMode,
 };
 use bevy::app::Update;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::{in_state, Window};
 use bevy_egui::{egui::{self, InnerResponse, Response, Ui}, EguiContexts};
 use bevy_inspector_egui::egui::{RichText, TextEdit};
-use chrono::{Days, NaiveDateTime};
 
 //use crate::fps::Fps;
-use crate::{apsis::ApsisBody, body::{BodyChildren, Diameter, Mass, Moon, OrbitSettings, Planet, RotationSpeed, Scale, SimPosition, Star, Velocity}, camera::PanOrbitCamera, constants::{DAY_IN_SECONDS, M_TO_AU, M_TO_UNIT}, egui_input_block::BlockInputPlugin, lock_on::LockOn, orbit_lines::OrbitOffset, physics::{apply_physics, SubSteps}, selection::SelectedEntity, setup::StartingTime, skybox::Cubemap, unit::format_length};
+use crate::{apsis::{ApsisBody, OsculatingElements, Precession}, body::{BodyChildren, Diameter, Mass, OrbitSettings, RotationSpeed, Scale, SimPosition, Velocity}, camera::PanOrbitCamera, constants::{M_TO_AU, M_TO_UNIT}, egui_input_block::BlockInputPlugin, lock_on::LockOn, orbit_lines::OrbitOffset, physics::{GravitySettings, Integrator, InteractionMode, SimTime, Solver, SubSteps, update_translations}, selection::SelectedEntity, setup::StartingTime, skybox::Cubemap, unit::format_length};
+use crate::adaptive::AdaptiveStep;
 use crate::billboard::BillboardSettings;
-use crate::body::BodyParent;
-use crate::constants::G;
+use crate::collision::{CollisionMode, CollisionSettings};
+use crate::body::{BodyKind, BodyParent};
+use crate::barycenter::Barycenter;
+use crate::soi::{SoiMode, SoiSettings};
+use crate::prediction::{Prediction, PredictionLength, PredictionSettings};
+use crate::lagrange::{LagrangePoint, LagrangePoints, SpawnAtLagrangePoint};
+use crate::maneuver::ManeuverSchedule;
 use crate::physics::Pause;
 use crate::SimState;
 use crate::speed::Speed;
 use crate::unit::format_seconds;
 
-#[derive(Resource, Reflect, Default)]
-pub struct SimTime(pub f32);
-
 #[derive(Resource, Reflect, Default)]
 pub struct Light {
     pub shadows_enabled: bool,
@@ -41,12 +44,18 @@ pub enum StepType {
 pub struct UiState {
     pub visible: bool,
     pub step_type: StepType,
-    pub show_debug: bool
+    pub show_debug: bool,
+    pub show_collisions: bool,
+    pub show_captures: bool,
+    pub show_eclipses: bool,
+    pub show_sky_events: bool,
+    pub show_transfers: bool,
+    pub show_porkchop: bool,
 }
 
 impl Default for UiState {
     fn default() -> Self {
-        UiState { visible: true, step_type: StepType::SUBSTEPS, show_debug: false}
+        UiState { visible: true, step_type: StepType::SUBSTEPS, show_debug: false, show_collisions: false, show_captures: false, show_eclipses: false, show_sky_events: false, show_transfers: false, show_porkchop: false }
     }
 }
 
@@ -56,19 +65,16 @@ impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
         app
             .init_resource::<UiState>()
-            .register_type::<SimTime>()
-            .init_resource::<SimTime>()
             .add_plugins(BlockInputPlugin)
             .add_systems(
                 Update,
-                (system_ui.after(time_ui), body_ui.after(system_ui), time_ui.after(apply_physics)).run_if(in_state(SimState::Simulation)),
+                (system_ui.after(time_ui), body_ui.after(system_ui), time_ui.after(update_translations)).run_if(in_state(SimState::Simulation)),
             );
     }
 }
 
 pub fn time_ui(
-    time: Res<Time>,
-    mut sim_time: ResMut<SimTime>,
+    sim_time: Res<SimTime>,
     mut egui_context: EguiContexts,
     mut speed: ResMut<Speed>,
     mut windows: Query<&mut Window>,
@@ -78,6 +84,8 @@ pub fn time_ui(
     mut state: ResMut<NextState<SimState>>,
     starting_time: Res<StartingTime>,
     mut sub_steps: ResMut<SubSteps>,
+    mut integrator: ResMut<Integrator>,
+    mut adaptive: ResMut<AdaptiveStep>,
     mut ui_state: ResMut<UiState>,
     diagnostics: Res<DiagnosticsStore>,
 ) {
@@ -85,13 +93,7 @@ pub fn time_ui(
         return;
     }
     let mut window = windows.single_mut();
-    if !pause.0 {
-        sim_time.0 += time.delta_seconds() * (((speed.0 * (sub_steps.0 as f64)) / (DAY_IN_SECONDS as f64)) as f32);
-    }
-    let date = NaiveDateTime::from_timestamp_millis(starting_time.0)
-        .unwrap()
-        .checked_add_days(Days::new((((sim_time.0 * 100.0).round()) / 100.0) as u64))
-        .unwrap();
+    let date = sim_time.date(&starting_time);
     egui::TopBottomPanel::bottom("time_panel")
         .resizable(false)
         .show(egui_context.ctx_mut(), |ui| {
@@ -167,6 +169,28 @@ pub fn time_ui(
                             }
                         }
                         ui.label(format!("({})", speed.format(1)));
+
+                        egui::ComboBox::from_id_source("integrator")
+                            .selected_text(integrator.name())
+                            .show_ui(ui, |ui| {
+                                for option in Integrator::ALL {
+                                    ui.selectable_value(&mut *integrator, option, option.name());
+                                }
+                            });
+                        if integrator.is_adaptive() {
+                            ui.label("Tolerance");
+                            let mut new_tolerance = format!("{:e}", adaptive.tolerance);
+                            if ui
+                                .add(TextEdit::singleline(&mut new_tolerance).desired_width(50.0))
+                                .changed()
+                            {
+                                if let Ok(new_tolerance_num) = new_tolerance.parse::<f64>() {
+                                    if new_tolerance_num > 0.0 {
+                                        adaptive.tolerance = new_tolerance_num;
+                                    }
+                                }
+                            }
+                        }
                         
                         if timestep_selected {
                             ui_state.step_type = StepType::TIMESTEPS
@@ -202,37 +226,17 @@ pub fn time_ui(
         });
 }
 
+/// Physics settings that can be changed in the Options panel
+#[derive(SystemParam)]
+pub struct PhysicsOptions<'w> {
+    gravity: ResMut<'w, GravitySettings>,
+    collisions: ResMut<'w, CollisionSettings>,
+    soi: ResMut<'w, SoiSettings>,
+}
+
 pub fn system_ui(
     mut egui_context: EguiContexts,
-    mut star_query: Query<(
-        &Name,
-        &BodyChildren,
-        Entity,
-        &mut Visibility
-    ), (
-        With<Star>,
-        Without<Planet>,
-        Without<Planet>
-    )>,
-    mut planet_query: Query<(
-        &Name,
-        &BodyChildren,
-        Entity,
-        &mut Visibility
-    ),(
-        With<Planet>,
-        Without<Star>,
-        Without<Moon>
-    )>,
-    mut moon_query: Query<(
-        &Name,
-        Entity,
-        &mut Visibility
-    ),(
-        With<Moon>,
-        Without<Planet>,
-        Without<Star>
-    )>,
+    bodies: Query<(Entity, &Name, Option<&BodyChildren>, Option<&BodyParent>, &BodyKind), With<Mass>>,
     //  mut camera: Query<&mut Camera>,
     mut light: Query<&mut PointLight>,
     mut state: ResMut<NextState<SimState>>,
@@ -243,7 +247,8 @@ pub fn system_ui(
     mut cubemap: ResMut<Cubemap>,
     mut billboard: ResMut<BillboardSettings>,
     mut ui_state: ResMut<UiState>,
-    mut orbit_offset: ResMut<OrbitOffset>
+    mut orbit_offset: ResMut<OrbitOffset>,
+    mut physics: PhysicsOptions,
 ) {
     if !ui_state.visible {
         return;
@@ -254,42 +259,19 @@ pub fn system_ui(
             .resizable(true)
             .show(egui_context.ctx_mut(), |ui| {
                 ui.heading("Bodies");
-                for (s_name, s_children, s_entity, _) in &mut star_query {
-                    let s_old_selected = selected_entity.entity == Some(s_entity);
-                    let mut s_selected = s_old_selected;
-                    body_tree(ui, &mut s_selected, s_name, true, |ui| {
-                        for planet_child in &s_children.0 {
-                            if let Ok((p_name, p_children, p_entity, _)) = planet_query.get_mut(*planet_child) {
-                                let p_old_selected = selected_entity.entity == Some(p_entity);
-                                let mut p_selected = p_old_selected;
-                                body_tree(ui, &mut p_selected, p_name, false, |ui| {
-                                    for moon_child in &p_children.0 {
-                                        if let Ok((m_name, m_entity, _)) = moon_query.get_mut(*moon_child) {
-                                            let m_old_selected = selected_entity.entity == Some(m_entity);
-                                            let mut m_selected = m_old_selected;
-                                            ui.horizontal(|ui| {
-                                                ui.toggle_value(&mut m_selected, m_name.as_str());
-                                            });
-                                            if m_selected && !m_old_selected {
-                                                selected_entity.change_entity(m_entity)
-                                            }
-                                        }
-                                    }
-                                });
-                                if p_selected && !p_old_selected {
-                                    selected_entity.change_entity(p_entity)
-                                }
-                            }
-                        }
-                    });
-                    if s_selected && !s_old_selected {
-                        selected_entity.change_entity(s_entity)
+                for (entity, _, _, parent, _) in &bodies {
+                    if parent.is_none() {
+                        body_tree_node(ui, entity, &bodies, &mut selected_entity, true);
                     }
                 }
                 ui.heading("Options");
                 ui.checkbox(&mut camera.hdr, "HDR/Bloom");
-                if let Ok(mut light) = light.get_single_mut() {
-                    ui.checkbox(&mut light.shadows_enabled, "Shadows");
+                //every star has its own light
+                let mut shadows = light.iter().any(|light| light.shadows_enabled);
+                if !light.is_empty() && ui.checkbox(&mut shadows, "Shadows").changed() {
+                    for mut light in &mut light {
+                        light.shadows_enabled = shadows;
+                    }
                 }
                 let skybox_enabled = skybox.is_some();
                 let mut skybox_setting = skybox_enabled;
@@ -303,6 +285,51 @@ pub fn system_ui(
                     cubemap.activated = true;
                 }
 
+                ui.horizontal(|ui| {
+                    ui.label("Gravity");
+                    for option in InteractionMode::ALL {
+                        ui.selectable_value(&mut physics.gravity.interaction_mode, option, option.name());
+                    }
+                });
+                ui.add_enabled_ui(physics.gravity.interaction_mode == InteractionMode::Full, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Solver");
+                        for option in Solver::ALL {
+                            ui.selectable_value(&mut physics.gravity.solver, option, option.name());
+                        }
+                    });
+                    if physics.gravity.solver == Solver::BarnesHut {
+                        ui.add(egui::Slider::new(&mut physics.gravity.theta, 0.0..=1.5).text("Opening angle θ"));
+                    }
+                });
+                ui.checkbox(&mut physics.gravity.parallel, "Multithreaded gravity");
+                ui.checkbox(&mut physics.gravity.relativity, "Relativistic correction (1PN)");
+                ui.horizontal(|ui| {
+                    ui.label("Softening length (km)");
+                    let mut new_softening = (physics.gravity.softening / 1000.0).to_string();
+                    if ui
+                        .add(TextEdit::singleline(&mut new_softening).desired_width(50.0))
+                        .changed()
+                    {
+                        if let Ok(new_softening_num) = new_softening.parse::<f64>() {
+                            if new_softening_num >= 0.0 {
+                                physics.gravity.softening = new_softening_num * 1000.0;
+                            }
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Collisions");
+                    for option in CollisionMode::ALL {
+                        ui.selectable_value(&mut physics.collisions.mode, option, option.name());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Re-parenting");
+                    for option in SoiMode::ALL {
+                        ui.selectable_value(&mut physics.soi.mode, option, option.name());
+                    }
+                });
                 ui.checkbox(&mut config.aabb.draw_all, "Draw Outlines");
                 ui.checkbox(&mut billboard.show, "Show Body Names");
                 if ui.checkbox(&mut orbit_offset.enabled, "Offset body to zero").changed() {
@@ -313,6 +340,24 @@ pub fn system_ui(
                 if ui.button("Open Debug Window").clicked() {
                     ui_state.show_debug = true; 
                 }
+                if ui.button("Open Collision Log").clicked() {
+                    ui_state.show_collisions = true;
+                }
+                if ui.button("Open Capture Log").clicked() {
+                    ui_state.show_captures = true;
+                }
+                if ui.button("Open Eclipse Log").clicked() {
+                    ui_state.show_eclipses = true;
+                }
+                if ui.button("Open Sky Events").clicked() {
+                    ui_state.show_sky_events = true;
+                }
+                if ui.button("Open Transfer Planner").clicked() {
+                    ui_state.show_transfers = true;
+                }
+                if ui.button("Open Porkchop Plot").clicked() {
+                    ui_state.show_porkchop = true;
+                }
                 ui.add_space(5.0);
                 ui.label("F11 - Toggle Fullscreen");
                 ui.label("F10 - Hide Ui");
@@ -332,6 +377,62 @@ pub fn system_ui(
     }
 }
 
+/// Adds a body and everything orbiting it to the tree, bodies without children are just a button
+fn body_tree_node(
+    ui: &mut Ui,
+    entity: Entity,
+    bodies: &Query<(Entity, &Name, Option<&BodyChildren>, Option<&BodyParent>, &BodyKind), With<Mass>>,
+    selected_entity: &mut SelectedEntity,
+    default_open: bool,
+) {
+    let Ok((_, name, children, _, kind)) = bodies.get(entity) else {
+        return;
+    };
+    let children = children.map(|children| children.0.as_slice()).unwrap_or_default();
+    let add_children = |ui: &mut Ui, selected_entity: &mut SelectedEntity| {
+        //planets and moons are listed directly, the small bodies of every kind get their own group
+        for child in children {
+            if bodies.get(*child).map_or(true, |(.., child_kind)| child_kind.group().is_none()) {
+                body_tree_node(ui, *child, bodies, selected_entity, false);
+            }
+        }
+        for group_kind in BodyKind::ALL {
+            let Some(group) = group_kind.group() else {
+                continue;
+            };
+            let members = children.iter().filter(|child| bodies.get(**child).map_or(false, |(.., child_kind)| *child_kind == group_kind)).collect::<Vec<_>>();
+            if members.is_empty() {
+                continue;
+            }
+            egui::CollapsingHeader::new(format!("{} ({})", group, members.len()))
+                .id_source((entity, group))
+                .show(ui, |ui| {
+                    for member in members {
+                        body_tree_node(ui, *member, bodies, selected_entity, false);
+                    }
+                });
+        }
+    };
+    if *kind == BodyKind::Barycenter { //nothing to select, it only groups the stars and their circumbinary planets
+        egui::CollapsingHeader::new(name.as_str())
+            .default_open(default_open)
+            .show(ui, |ui| add_children(ui, selected_entity));
+        return;
+    }
+    let old_selected = selected_entity.entity == Some(entity);
+    let mut selected = old_selected;
+    if children.is_empty() {
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut selected, name.as_str());
+        });
+    } else {
+        body_tree(ui, &mut selected, name, default_open, |ui| add_children(ui, selected_entity));
+    }
+    if selected && !old_selected {
+        selected_entity.change_entity(entity)
+    }
+}
+
 fn body_tree<R>(
     ui: &mut Ui,
     mut selected: &mut bool,
@@ -354,23 +455,36 @@ fn body_tree<R>(
 fn body_ui(
     mut egui_context: EguiContexts,
     mut commands: Commands,
-    mut query: Query<(&Name, Entity, &SimPosition, &Velocity, &RotationSpeed, &Diameter, &mut OrbitSettings, &mut Mass, &Scale, &mut Transform, Option<&mut ApsisBody>, Option<&BodyChildren>, Option<&BodyParent>)>,
+    mut query: Query<(&Name, Entity, &SimPosition, &Velocity, &RotationSpeed, &Diameter, &mut OrbitSettings, &mut Mass, &Scale, &mut Transform, Option<&mut ApsisBody>, Option<&BodyChildren>, Option<&BodyParent>), Without<Barycenter>>,
+    barycenters: Query<(&SimPosition, &Velocity, &Name, &Mass), With<Barycenter>>,
+    kinds: Query<&BodyKind>,
+    elements: Query<&OsculatingElements>,
     camera: Query<(&Camera, &Transform, Without<Velocity>)>,
     selected_entity: Res<SelectedEntity>,
     ui_state: Res<UiState>,
+    starting_time: Res<StartingTime>,
+    predicted: Query<(), With<Prediction>>,
+    mut prediction: ResMut<PredictionSettings>,
+    lagrange: Query<(), With<LagrangePoints>>,
+    mut lagrange_spawns: EventWriter<SpawnAtLagrangePoint>,
+    gravity: Res<GravitySettings>,
+    maneuvers: Query<&ManeuverSchedule>,
 ) {
     if !ui_state.visible {
         return;
     }
     if let Some(entity) = selected_entity.entity {
-        let mut parent: Option<(&SimPosition, &Velocity, &Name, Mass)> = None;
+        let mut parent: Option<(&SimPosition, &Velocity, &Name, Mass, bool)> = None;
         let mut selected: Option<(&Name, Entity, &SimPosition, &Velocity, &RotationSpeed, &Diameter, Mut<OrbitSettings>, Mut<Transform>, Mut<Mass>, Option<Mut<ApsisBody>>, &Scale, Option<&BodyChildren>)> = None;
         let mut s_children: Vec<(Entity, Mut<OrbitSettings>)> = vec![];
+        let parent_entity = query.get(entity).ok().and_then(|(.., maybe_parent)| maybe_parent.map(|p| p.0));
+        //stars of a multiple system and circumbinary planets orbit a barycenter
+        if let Some((pos, velocity, name, mass)) = parent_entity.and_then(|p| barycenters.get(p).ok()) {
+            parent = Some((pos, velocity, name, mass.clone(), true));
+        }
         for (name, b_entity, pos, velocity, rotation_speed, diameter, orbit, mass, scale, transform, apsis, children, maybe_parent) in query.iter_mut() {
-            if let Some(children) = children { //check for the parent of the selected entity
-                if children.0.contains(&entity) {
-                    parent = Some((pos, velocity, name, mass.clone()));
-                }
+            if parent_entity == Some(b_entity) { //check for the parent of the selected entity
+                parent = Some((pos, velocity, name, mass.clone(), false));
             }
             if b_entity == entity { //check for the selected entity
                 selected = Some((name, b_entity, pos, velocity, rotation_speed, diameter, orbit, transform, mass, apsis, scale, children));
@@ -386,6 +500,9 @@ fn body_ui(
                 .resizable(true)
                 .show(egui_context.ctx_mut(), |ui| {
                     ui.heading(name.as_str());
+                    if let Ok(kind) = kinds.get(entity) {
+                        ui.label(kind.name());
+                    }
 
                     //Mass block
                     ui.label(RichText::new("Mass").size(16.0).underline());
@@ -433,7 +550,7 @@ fn body_ui(
 
                     // Velocity Orbit Velocity around parent
                     let actual_velocity = match &parent {
-                        Some((_, vel, _, _)) => (vel.0 - velocity.0).length() / 1000.0,
+                        Some((_, vel, _, _, _)) => (vel.0 - velocity.0).length() / 1000.0,
                         None => velocity.0.length() / 1000.0,
                     };
                     ui.label(RichText::new("Orbital Velocity").size(16.0).underline());
@@ -444,14 +561,32 @@ fn body_ui(
                         }
                     });
                     
-                    let mut new_apsis = None;
-                    if let Some((_, _, _, p_mass)) = parent {
-                        if let Some(apsis) = apsis {
-                            let distance = ((apsis.aphelion.distance + apsis.perihelion.distance) / 2.0) as f64;                           
-                            orbit.period = 2.0 * std::f64::consts::PI * f64::sqrt(f64::powf(distance, 3.0) / (G * (p_mass.0 + mass.0)));
-                            ui.label(RichText::new("Orbital Period").size(16.0).underline());
-                            ui.label(format!("{}", format_seconds(orbit.period)));
-                            new_apsis = Some(apsis);
+                    if let Ok(schedule) = maneuvers.get(entity) {
+                        ui.label(RichText::new("Δv Budget").size(16.0).underline());
+                        ui.label(format!("{:.3} of {:.3} km/s left", schedule.remaining() / 1000.0, schedule.budget / 1000.0));
+                        ui.label(RichText::new("Upcoming Burns").size(16.0).underline());
+                        if schedule.upcoming().next().is_none() {
+                            ui.label("No burns left");
+                        }
+                        for burn in schedule.upcoming() {
+                            let date = SimTime(burn.time).date(&starting_time).format("%d.%m.%Y %H:%M");
+                            let duration = if burn.duration == 0.0 { "impulsive".to_string() } else { format_seconds(burn.duration) };
+                            ui.label(RichText::new(format!("{} ({})", date, duration)).strong());
+                            let [prograde, normal, radial] = (burn.delta_v / 1000.0).to_array();
+                            ui.label(format!("{:.3} km/s: {:.3} prograde, {:.3} normal, {:.3} radial", burn.delta_v.length() / 1000.0, prograde, normal, radial));
+                            if burn.progress > 0.0 {
+                                ui.label(format!("Burning, {:.0}% done", burn.progress * 100.0));
+                            }
+                        }
+                    }
+                    
+                    let osculating = elements.get(entity).ok().filter(|_| parent.is_some());
+                    if let Some(osculating) = osculating {
+                        ui.label(RichText::new("Orbital Period").size(16.0).underline());
+                        if osculating.period.is_finite() {
+                            ui.label(format!("{}", format_seconds(osculating.period)));
+                        } else {
+                            ui.label("Unbound");
                         }
                     }
                     
@@ -464,13 +599,41 @@ fn body_ui(
                     ui.label(format!("{:.3} au", c_distance_in_au / 10000.0));
 
                     // Distance to parent
-                    if let Some((parent_pos, _, p_name, _)) = parent {
+                    if let Some((parent_pos, _, p_name, _, _)) = parent {
                         ui.label(RichText::new(format!("Distance to {}", p_name)).size(16.0).underline());
                         let distance_in_m = parent_pos.0.distance(pos.0);
                         ui.label(format!("{}", format_length(distance_in_m as f32)));
                         ui.label(format!("{:.3} au", distance_in_m * (M_TO_AU as f64)));
                         
-                        if let Some(mut apsis) = new_apsis {
+                        if let Some(osculating) = osculating {
+                            let elements = osculating.elements;
+                            ui.label(RichText::new(format!("Orbital Elements ({})", p_name)).size(16.0).underline());
+                            egui::Grid::new("orbital_elements").show(ui, |ui| {
+                                ui.label("Semi-major Axis");
+                                ui.label(format!("{:.3} au", elements.semi_major_axis * M_TO_AU as f64));
+                                ui.end_row();
+                                ui.label("Eccentricity");
+                                ui.label(format!("{:.5}", elements.eccentricity));
+                                ui.end_row();
+                                ui.label("Inclination");
+                                ui.label(format!("{:.3}°", elements.inclination.to_degrees()));
+                                ui.end_row();
+                                ui.label("Ascending Node");
+                                ui.label(format!("{:.3}°", elements.ascending_node.to_degrees()));
+                                ui.end_row();
+                                ui.label("Arg. of Periapsis");
+                                ui.label(format!("{:.3}°", elements.argument_of_periapsis.to_degrees()));
+                                ui.end_row();
+                                ui.label("True Anomaly");
+                                ui.label(format!("{:.3}°", osculating.true_anomaly.to_degrees()));
+                                ui.end_row();
+                                ui.label("Mean Anomaly");
+                                ui.label(format!("{:.3}°", elements.mean_anomaly.to_degrees()));
+                                ui.end_row();
+                            });
+                        }
+
+                        if let Some(mut apsis) = apsis {
                             //Apsis
                             ui.label(RichText::new(format!("Periapsis ({})", p_name)).size(16.0).underline());
                             ui.label(format!("{}", format_length(apsis.perihelion.distance)));
@@ -479,9 +642,25 @@ fn body_ui(
                             ui.label(RichText::new(format!("Apoapsis ({})", p_name)).size(16.0).underline());
                             ui.label(format!("{}", format_length(apsis.aphelion.distance)));
                             ui.label(format!("{:.3} au", apsis.aphelion.distance * M_TO_AU));
+                            ui.label(RichText::new("Periapsis Precession").size(16.0).underline());
+                            match apsis.precession.arcsec_per_century() {
+                                Some(rate) => ui.label(format!("{:.2}″/century ({} passages)", rate, apsis.precession.passages)),
+                                None => ui.label("Waiting for two periapsis passages"),
+                            };
+                            egui::CollapsingHeader::new(format!("Apsis Passages ({})", apsis.passages.len())).show(ui, |ui| {
+                                if apsis.passages.is_empty() {
+                                    ui.label("No passage yet");
+                                }
+                                for passage in apsis.passages.iter().rev() {
+                                    ui.label(RichText::new(format!("{} {}", passage.apsis_type.name(), SimTime(passage.time).date(&starting_time).format("%d.%m.%Y %H:%M"))).strong());
+                                    ui.label(format!("{} ({:.3} au)", format_length(passage.distance), passage.distance * M_TO_AU));
+                                }
+                            });
                             if ui.button("Reset Apsides").clicked() {
                                apsis.aphelion.distance = 0.0;
                                apsis.perihelion.distance = 0.0; 
+                               apsis.precession = Precession::default();
+                               apsis.passages.clear();
                             }
                             
                             let mut new_draw_lines = orbit.draw_lines;
@@ -493,6 +672,51 @@ fn body_ui(
                                 }   
                             }
                         }
+
+                        let mut predict = predicted.contains(entity);
+                        if ui.checkbox(&mut predict, "Predict Trajectory").changed() {
+                            if predict {
+                                commands.entity(entity).insert(Prediction::default());
+                            } else {
+                                commands.entity(entity).remove::<Prediction>();
+                            }
+                        }
+                        if predict {
+                            ui.horizontal(|ui| {
+                                if ui.add(egui::DragValue::new(&mut prediction.amount).speed(0.1).clamp_range(0.1..=1000.0)).changed() {
+                                    prediction.dirty = true;
+                                }
+                                for option in PredictionLength::ALL {
+                                    if ui.selectable_value(&mut prediction.length, option, option.name()).changed() {
+                                        prediction.dirty = true;
+                                    }
+                                }
+                            });
+                        }
+
+                        if mass.0 > 0.0 {
+                            let mut show_lagrange = lagrange.contains(entity);
+                            if ui.checkbox(&mut show_lagrange, "Show Lagrange Points").changed() {
+                                if show_lagrange {
+                                    commands.entity(entity).insert(LagrangePoints);
+                                } else {
+                                    commands.entity(entity).remove::<LagrangePoints>();
+                                }
+                            }
+                            if show_lagrange {
+                                ui.label("Spawn Test Body");
+                                ui.horizontal(|ui| {
+                                    for point in LagrangePoint::ALL {
+                                        if ui.button(point.name()).clicked() {
+                                            lagrange_spawns.send(SpawnAtLagrangePoint { body: entity, point });
+                                        }
+                                    }
+                                });
+                                if gravity.interaction_mode == InteractionMode::Hierarchical {
+                                    ui.label(format!("Spawning switches to the {} mode, test bodies only feel {} in it", InteractionMode::Full.name(), name));
+                                }
+                            }
+                        }
                     }
                 
                     if s_children.iter().count() > 0 {
@@ -0,0 +1,79 @@
+use bevy::app::{App, Plugin, Update};
+use bevy::core::Name;
+use bevy::math::DVec3;
+use bevy::prelude::{Entity, in_state, IntoSystemConfigs, Query, Res, ResMut, Resource};
+use bevy_egui::{egui, EguiContexts};
+use bevy_egui::egui::RichText;
+
+use crate::physics::{PhysicsBody, SimTime};
+use crate::setup::StartingTime;
+use crate::SimState;
+use crate::ui::{system_ui, UiState};
+
+pub struct WatchdogPlugin;
+
+impl Plugin for WatchdogPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Watchdog>()
+            .add_systems(Update, (watchdog_window.after(system_ui)).run_if(in_state(SimState::Simulation)));
+    }
+
+}
+
+/// The first body whose state stopped being finite, stays visible in the ui until it is dismissed
+#[derive(Debug, Clone)]
+pub struct NumericalFailure {
+    pub entity: Entity,
+    pub time: f64,
+    pub position: DVec3,
+    pub velocity: DVec3,
+}
+
+#[derive(Resource, Default)]
+pub struct Watchdog {
+    pub failure: Option<NumericalFailure>,
+}
+
+/// Returns the first body with a NaN or infinite position or velocity
+pub fn find_non_finite(bodies: &[PhysicsBody], time: f64) -> Option<NumericalFailure> {
+    bodies.iter()
+        .find(|body| !body.position.is_finite() || !body.velocity.is_finite())
+        .map(|body| NumericalFailure {
+            entity: body.entity,
+            time,
+            position: body.position,
+            velocity: body.velocity,
+        })
+}
+
+fn watchdog_window(
+    mut egui_ctx: EguiContexts,
+    mut watchdog: ResMut<Watchdog>,
+    names: Query<&Name>,
+    starting_time: Res<StartingTime>,
+    ui_state: Res<UiState>,
+) {
+    if !ui_state.visible {
+        return;
+    }
+    let Some(failure) = watchdog.failure.clone() else {
+        return;
+    };
+    let name = names.get(failure.entity).map(|name| name.to_string()).unwrap_or_else(|_| format!("{:?}", failure.entity));
+    let mut open = true;
+    egui::Window::new("Simulation Error")
+        .open(&mut open)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.label(RichText::new(format!("The state of {} is no longer finite", name)).strong());
+            ui.label(format!("Date: {}", SimTime(failure.time).date(&starting_time).format("%d.%m.%Y %H:%M")));
+            ui.label(format!("Position: {}", failure.position));
+            ui.label(format!("Velocity: {}", failure.velocity));
+            ui.add_space(5.0);
+            ui.label("The simulation was paused at the last valid state. Increasing the softening length or lowering the timestep usually helps.");
+        });
+    if !open {
+        watchdog.failure = None;
+    }
+}